@@ -0,0 +1,48 @@
+// Blends four terrain layer colors using the weights stored in a splat map.
+// Red, green, blue and alpha of the splat map select sand, grass, rock and snow.
+//
+// Works with both forward and deferred rendering.
+
+#import bevy_pbr::pbr_fragment::pbr_input_from_standard_material
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+struct TerrainLayers {
+    colors: array<vec4<f32>, 4>,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(100) var splat_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(101) var splat_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(102) var<uniform> terrain_layers: TerrainLayers;
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    let weights = textureSample(splat_texture, splat_sampler, in.uv);
+    let total = max(weights.r + weights.g + weights.b + weights.a, 0.0001);
+    let color = (terrain_layers.colors[0] * weights.r +
+        terrain_layers.colors[1] * weights.g +
+        terrain_layers.colors[2] * weights.b +
+        terrain_layers.colors[3] * weights.a) / total;
+    pbr_input.material.base_color = vec4(color.rgb, 1.0);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+    return out;
+}
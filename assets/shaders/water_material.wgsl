@@ -1,14 +1,31 @@
-// A shader that creates water ripples by overlaying 4 normal maps on top of one
-// another.
+// A shader that moves the water surface with a few sine waves and creates water
+// ripples by overlaying 4 normal maps on top of one another.
 //
-// This is used in the `ssr` example. It only supports deferred rendering.
+// The waves have to stay the same as `WaterWaves::height` in water.rs, which the
+// physics and the camera use. It works with both deferred (with ssr) and forward
+// rendering. The time comes from the settings instead of the globals, since
+// those are bound differently in the two.
 
 #import bevy_pbr::{
-    pbr_deferred_functions::deferred_output,
+    mesh_functions,
+    view_transformations::position_world_to_clip,
     pbr_fragment::pbr_input_from_standard_material,
-    prepass_io::{VertexOutput, FragmentOutput},
 }
-#import bevy_render::globals::Globals
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    pbr_deferred_functions::deferred_output,
+    prepass_io::{Vertex, VertexOutput, FragmentOutput},
+}
+#else
+#import bevy_pbr::{
+    forward_io::{Vertex, VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+const GRAVITY: f32 = 9.81;
+const TAU: f32 = 6.28318530718;
 
 // Parameters to the water shader.
 struct WaterSettings {
@@ -19,19 +36,124 @@ struct WaterSettings {
     octave_scales: vec4<f32>,
     // How high the waves are in each octave.
     octave_strengths: vec4<f32>,
+    // Every wave as direction (x, z), wavelength and amplitude.
+    waves: array<vec4<f32>, 4>,
+    // How far from the middle of the mesh each wave is drawn.
+    wave_reach: vec4<f32>,
+    // The time this frame and the last one.
+    time: vec4<f32>,
+    // xy: absolute position of the world origin, zw: corner of the shore map.
+    origin: vec4<f32>,
+    // x: size of the shore map, 0 without one, yz: uv of the world origin.
+    shore_size: vec4<f32>,
 }
 
-@group(0) @binding(1) var<uniform> globals: Globals;
+// The uvs repeat every this many meters, 2 * WATER_HALF_SIZE in water.rs.
+const UV_PERIOD: f32 = 20000.0;
 
 @group(#{MATERIAL_BIND_GROUP}) @binding(100) var water_normals_texture: texture_2d<f32>;
 @group(#{MATERIAL_BIND_GROUP}) @binding(101) var water_normals_sampler: sampler;
 @group(#{MATERIAL_BIND_GROUP}) @binding(102) var<uniform> water_settings: WaterSettings;
+@group(#{MATERIAL_BIND_GROUP}) @binding(103) var shore_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(104) var shore_sampler: sampler;
+
+// Height of the waves and its slope along x and z at a world position. Each wave fades out
+// towards its reach from the middle of the mesh, where the cells get too wide for it.
+fn sample_waves(world_xz: vec2<f32>, mesh_center: vec2<f32>, time: f32) -> vec3<f32> {
+    let position = world_xz + water_settings.origin.xy;
+    let distance = length(world_xz - mesh_center);
+    var result = vec3(0.0);
+    for (var i = 0; i < 4; i++) {
+        let wave = water_settings.waves[i];
+        let k = TAU / wave.z;
+        let omega = sqrt(GRAVITY * k);
+        let phase = k * dot(wave.xy, position) - omega * time;
+        let amplitude = wave.w * clamp(2.0 * (1.0 - distance / water_settings.wave_reach[i]), 0.0, 1.0);
+        result.x += amplitude * sin(phase);
+        result.y += amplitude * k * wave.x * cos(phase);
+        result.z += amplitude * k * wave.y * cos(phase);
+    }
+    return result;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    var world_position = mesh_functions::mesh_position_local_to_world(
+        world_from_local,
+        vec4(vertex.position, 1.0),
+    );
+    let waves = sample_waves(world_position.xz, world_from_local[3].xz, water_settings.time.x);
+    world_position.y += waves.x;
+
+    out.world_position = world_position;
+    out.position = position_world_to_clip(world_position.xyz);
+
+#ifdef UNCLIPPED_DEPTH_ORTHO_EMULATION
+    out.unclipped_depth = out.position.z;
+    out.position.z = min(out.position.z, 1.0);
+#endif
+
+#ifdef VERTEX_UVS_A
+    // From the absolute position, so the ripples stay put while the mesh follows the camera.
+    out.uv = world_position.xz / UV_PERIOD + water_settings.shore_size.yz;
+#endif
+
+    // The prepass only has normals when they're needed, the main pass always does.
+#ifdef PREPASS_PIPELINE
+#ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
+    out.world_normal = normalize(vec3(-waves.y, 1.0, -waves.z));
+#endif
+#else
+    out.world_normal = normalize(vec3(-waves.y, 1.0, -waves.z));
+#endif
+
+#ifdef VERTEX_TANGENTS
+#ifdef PREPASS_PIPELINE
+#ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index,
+    );
+#endif
+#else
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent,
+        vertex.instance_index,
+    );
+#endif
+#endif
+
+#ifdef MOTION_VECTOR_PREPASS
+    let previous_world_from_local = mesh_functions::get_previous_world_from_local(vertex.instance_index);
+    var previous_world_position = mesh_functions::mesh_position_local_to_world(
+        previous_world_from_local,
+        vec4(vertex.position, 1.0),
+    );
+    previous_world_position.y += sample_waves(
+        previous_world_position.xz,
+        previous_world_from_local[3].xz,
+        water_settings.time.y,
+    ).x;
+    out.previous_world_position = previous_world_position;
+#endif
+
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    out.instance_index = vertex.instance_index;
+#endif
+
+    return out;
+}
 
 // Samples a single octave of noise and returns the resulting normal.
 fn sample_noise_octave(uv: vec2<f32>, strength: f32) -> vec3<f32> {
     let N = textureSample(water_normals_texture, water_normals_sampler, uv).rbg * 2.0 - 1.0;
     // This isn't slerp, but it's good enough.
-    return normalize(mix(vec3(0.0, 1.0, 0.0), N, strength)); 
+    return normalize(mix(vec3(0.0, 1.0, 0.0), N, strength));
 }
 
 // Samples all four octaves of noise and returns the resulting normal.
@@ -48,12 +170,37 @@ fn sample_noise(uv: vec2<f32>, time: f32) -> vec3<f32> {
     );
 }
 
+// How much foam there is where the water meets the terrain.
+fn shore_foam(world_xz: vec2<f32>, ripples: vec3<f32>) -> f32 {
+    let size = water_settings.shore_size.x;
+    let uv = (world_xz - water_settings.origin.zw) / max(size, 1.0);
+    var foam = textureSample(shore_texture, shore_sampler, uv).r;
+    if size <= 0.0 || any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+        foam = 0.0;
+    }
+    // Broken up by the ripples, so it isn't a smooth band.
+    return smoothstep(0.4, 0.8, foam * (0.75 + (ripples.x + ripples.z) * 2.0));
+}
+
 @fragment
 fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
     // Create the PBR input.
     var pbr_input = pbr_input_from_standard_material(in, is_front);
-    // Bump the normal.
-    pbr_input.N = sample_noise(in.uv, globals.time);
+    // Bump the normal of the waves with the ripples.
+    let ripples = sample_noise(in.uv, water_settings.time.x);
+    pbr_input.N = normalize(in.world_normal + vec3(ripples.x, 0.0, ripples.z));
+
+    let foam = shore_foam(in.world_position.xz, ripples);
+    pbr_input.material.base_color = mix(pbr_input.material.base_color, vec4(0.9, 0.9, 0.9, 1.0), foam);
+    pbr_input.material.perceptual_roughness = mix(pbr_input.material.perceptual_roughness, 0.8, foam);
+
+#ifdef PREPASS_PIPELINE
     // Send the rest to the deferred shader.
     return deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+#endif
 }
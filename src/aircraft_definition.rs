@@ -0,0 +1,303 @@
+/*
+Aircraft definitions live in assets/aircraft/<name>.json, the one to fly is picked with the
+`aircraft` field in settings.json.
+Positions are in meters, local to the aircraft (x = right, y = up, -z = forward).
+*/
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// A point that floats on water, e.g. a float, hull or fuselage section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FloatPoint {
+    pub position: Vec3,
+    // Displaced volume in m³ when fully submerged.
+    pub volume: f32,
+    // How deep the point has to be below the surface to be fully submerged.
+    pub draft: f32,
+}
+
+// RPM, thrust in N, fuel flow in kg/s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineDefinition {
+    pub max_thrust: f32,
+    pub idle_rpm: f32,
+    pub max_rpm: f32,
+    // How fast the RPM follows the throttle, in RPM per second.
+    pub spool_rate: f32,
+    pub idle_fuel_flow: f32,
+    pub max_fuel_flow: f32,
+}
+
+// Flap angles in degrees, `rate` in degrees per second. The coefficients are added to the lift,
+// drag and pitching moment coefficients per degree of flap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlapDefinition {
+    pub detents: Vec<f32>,
+    pub rate: f32,
+    pub lift_per_degree: f32,
+    pub drag_per_degree: f32,
+    pub pitch_per_degree: f32,
+}
+
+// Slats, speed brakes and spoilers. The coefficients are added when fully extended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceDefinition {
+    // Seconds from fully retracted to fully extended.
+    pub extension_time: f32,
+    pub lift: f32,
+    pub drag: f32,
+    pub pitch: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GearDefinition {
+    pub retractable: bool,
+    // Seconds from fully retracted to down and locked.
+    pub transition_time: f32,
+    // Added to the drag coefficient when fully extended.
+    pub drag: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlAxis {
+    Pitch,
+    Roll,
+    Yaw,
+    Flaps,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimatedState {
+    GearDown,
+    CanopyOpen,
+    DoorOpen,
+    SpeedBrake,
+    Spoilers,
+}
+
+// The clip's time follows the control, its start being full negative deflection (or retracted
+// flaps) and its end full positive deflection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurfaceClip {
+    pub clip: String,
+    pub axis: ControlAxis,
+}
+
+// Played forward when the state turns on and backward when it turns off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateClip {
+    pub clip: String,
+    pub state: AnimatedState,
+}
+
+// Clip names refer to animations in the aircraft's glTF file, missing clips are skipped.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationBindings {
+    // Looped, at normal speed when the engine turns at `propeller_rpm`.
+    pub propeller: Vec<String>,
+    pub propeller_rpm: f32,
+    pub surfaces: Vec<SurfaceClip>,
+    pub states: Vec<StateClip>,
+}
+
+// Sound files inside assets/, aircraft without one are silent there. The looped sounds are pitched
+// and faded by the simulation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundBank {
+    // Looped, recorded at `engine_rpm`.
+    pub engine: Option<String>,
+    pub engine_rpm: f32,
+    // Looped, at full volume from `wind_full_speed` (m/s) up.
+    pub wind: Option<String>,
+    pub wind_full_speed: f32,
+    // Looped while the angle of attack is above `stall_warning_angle` degrees in flight.
+    pub stall_horn: Option<String>,
+    pub stall_warning_angle: f32,
+    // Looped while the gear or the flaps move.
+    pub gear_motor: Option<String>,
+    pub flap_motor: Option<String>,
+    pub tire_screech: Option<String>,
+    pub crash: Option<String>,
+}
+
+impl Default for SoundBank {
+    fn default() -> Self {
+        Self {
+            engine: None,
+            engine_rpm: 2000.,
+            wind: None,
+            wind_full_speed: 80.,
+            stall_horn: None,
+            stall_warning_angle: 14.,
+            gear_motor: None,
+            flap_motor: None,
+            tire_screech: None,
+            crash: None,
+        }
+    }
+}
+
+// Masses in kg.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FuelTank {
+    pub name: String,
+    pub position: Vec3,
+    pub capacity: f32,
+    // Tanks with a lower priority are emptied first, tanks with the same priority evenly.
+    pub feed_priority: u32,
+}
+
+// A seat or cargo area, masses in kg.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadStation {
+    pub name: String,
+    pub position: Vec3,
+    pub max_mass: f32,
+    pub default_mass: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Part {
+    Wings,
+    Engine,
+    Gear,
+    ControlSurfaces,
+    Fuselage,
+}
+
+// A box in aircraft space, contacts inside of it hit `part`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartRegion {
+    pub part: Part,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+// Sink rates in m/s, impulse in Ns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpactLimits {
+    pub touchdown_sink_rate: f32,
+    pub hard_landing_sink_rate: f32,
+    pub gear_collapse_sink_rate: f32,
+    // Anything but the gear hitting harder than this is a crash.
+    pub crash_impulse: f32,
+}
+
+#[derive(Resource, Debug, Serialize, Deserialize)]
+pub struct AircraftDefinition {
+    pub name: String,
+    // glTF file inside assets/
+    pub model: String,
+    // Mass in kg and center of mass without fuel and payload.
+    pub empty_mass: f32,
+    pub empty_center_of_mass: Vec3,
+    pub engine: EngineDefinition,
+    // m² and m
+    pub wing_area: f32,
+    pub mean_chord: f32,
+    // Lift coefficient of the clean wing with the fuselage level, its slope per radian of angle of
+    // attack and the most it gets to before the wing stalls.
+    pub lift_at_zero_alpha: f32,
+    pub lift_slope: f32,
+    pub max_lift: f32,
+    // Drag coefficient per lift coefficient squared, 1 / (π · aspect ratio · span efficiency).
+    pub induced_drag: f32,
+    // Moment coefficients (on `wing_area` and `mean_chord`) turning the nose into the airflow, per
+    // radian of angle of attack and sideslip.
+    pub pitch_stability: f32,
+    pub yaw_stability: f32,
+    // Moment coefficients at full pitch, yaw and roll input, and the damping of the pitch, yaw and
+    // roll rates (per mean_chord / 2V radians per second).
+    pub control_power: Vec3,
+    pub rotation_damping: Vec3,
+    // Drag coefficients of the airframe itself along its x (sideways), y (up and down) and z (nose
+    // to tail) axes, on `wing_area`. This is what crosswinds and gusts push against.
+    #[serde(default = "default_airframe_drag")]
+    pub airframe_drag: Vec3,
+    pub flaps: FlapDefinition,
+    // Slats come out together with the first flap detent.
+    pub slats: DeviceDefinition,
+    pub speed_brake: DeviceDefinition,
+    pub spoilers: DeviceDefinition,
+    pub gear: GearDefinition,
+    pub animations: AnimationBindings,
+    #[serde(default)]
+    pub sounds: SoundBank,
+    pub tanks: Vec<FuelTank>,
+    pub stations: Vec<PayloadStation>,
+    pub max_takeoff_mass: f32,
+    // Polygon of allowed (center of mass z, total mass) pairs. z grows towards the tail.
+    pub cg_envelope: Vec<Vec2>,
+    // Seaplanes can land on and take off from water, everything else ditches.
+    pub seaplane: bool,
+    pub floats: Vec<FloatPoint>,
+    // Hydrodynamic drag coefficient per float, scaled by how deep it is submerged.
+    pub water_drag: f32,
+    // Touching water faster than this (m/s) is a crash.
+    pub max_water_sink_rate: f32,
+    // Checked in order, contacts outside of every region hit the fuselage.
+    pub parts: Vec<PartRegion>,
+    pub impact_limits: ImpactLimits,
+}
+
+fn default_airframe_drag() -> Vec3 {
+    Vec3::new(0.6, 0.8, 0.03)
+}
+
+impl AircraftDefinition {
+    pub fn fetch(name: &str) -> Self {
+        Self::load(name).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // For the aircraft list, where one broken file shouldn't take the whole game down. The error
+    // names the file.
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = format!("assets/aircraft/{name}.json");
+        let json_data = fs::read_to_string(&path).map_err(|error| format!("{path}: {error}"))?;
+        serde_json::from_str(&json_data).map_err(|error| format!("{path}: {error}"))
+    }
+
+    // Past the stall, the lift falls off to that of a flat plate over about 10 degrees.
+    pub fn lift_coefficient(&self, angle_of_attack: f32) -> f32 {
+        let linear = self.lift_at_zero_alpha + self.lift_slope * angle_of_attack;
+        if linear.abs() <= self.max_lift {
+            return linear;
+        }
+        let past_stall = (linear.abs() - self.max_lift) / self.lift_slope;
+        let flat_plate = (2. * angle_of_attack).sin();
+        self.max_lift
+            .copysign(linear)
+            .lerp(flat_plate, (past_stall / 10_f32.to_radians()).min(1.))
+    }
+
+    pub fn cg_within_envelope(&self, center_of_mass_z: f32, mass: f32) -> bool {
+        // Even-odd rule
+        let point = Vec2::new(center_of_mass_z, mass);
+        let mut inside = false;
+        let mut previous = *self.cg_envelope.last().unwrap_or(&Vec2::ZERO);
+        for &current in &self.cg_envelope {
+            if (current.y > point.y) != (previous.y > point.y)
+                && point.x
+                    < (previous.x - current.x) * (point.y - current.y) / (previous.y - current.y)
+                        + current.x
+            {
+                inside = !inside;
+            }
+            previous = current;
+        }
+        inside
+    }
+
+    pub fn part_at(&self, local_point: Vec3) -> Part {
+        self.parts
+            .iter()
+            .find(|region| {
+                local_point.cmpge(region.min).all() && local_point.cmple(region.max).all()
+            })
+            .map_or(Part::Fuselage, |region| region.part)
+    }
+}
@@ -1,17 +1,195 @@
-use crate::{Aircraft, InputAxis};
+use crate::{
+    Aircraft, InputAxis,
+    aircraft_definition::{AircraftDefinition, EngineDefinition},
+    damage::{Crashed, Damage},
+};
 use avian3d::prelude::*;
 use bevy::prelude::*;
 
+// The air's movement, set by the weather (see weather.rs). `gust` is the turbulence on top of the
+// steady wind, it changes every frame.
+#[derive(Resource, Debug, Default)]
+pub struct Wind {
+    // m/s, the direction the air moves to.
+    pub velocity: Vec3,
+    // Strength of the gusts in m/s.
+    pub turbulence: f32,
+    pub gust: Vec3,
+}
+
+impl Wind {
+    pub fn at(&self, altitude: f32) -> Vec3 {
+        // Slower close to the ground, full strength from about 300 m up.
+        let profile = (altitude.max(1.) / 300.).min(1.).powf(1. / 7.);
+        self.velocity * profile + self.gust
+    }
+}
+
+// Air data and accelerations, derived from the physics state every frame.
+#[derive(Component, Debug, Default)]
+pub struct FlightData {
+    // Velocity relative to the air in m/s, in world space.
+    pub air_velocity: Vec3,
+    // True airspeed in m/s
+    pub airspeed: f32,
+    // Radians, positive when the air comes from below / from the right.
+    pub angle_of_attack: f32,
+    pub sideslip: f32,
+    // ½ρV² in Pa
+    pub dynamic_pressure: f32,
+    // m/s, positive when climbing
+    pub vertical_speed: f32,
+    // Load factor along the aircraft's up axis, 1 in level flight.
+    pub g_load: f32,
+    pub acceleration: Vec3,
+    last_velocity: Vec3,
+}
+
+// ISA air density at an altitude in meters.
+pub fn air_density(altitude: f32) -> f32 {
+    1.225 * (1. - 2.255_77e-5 * altitude.clamp(0., 11_000.)).powf(4.2559)
+}
+
+// A few sines at unrelated frequencies, which is random enough for gusts and stays the same between
+// runs.
+pub fn update_turbulence(time: Res<Time>, mut wind: ResMut<Wind>) {
+    let t = time.elapsed_secs();
+    let noise = |phase: f32| {
+        ((t * 0.31 + phase).sin()
+            + (t * 0.73 + phase * 1.7).sin() * 0.6
+            + (t * 1.9 + phase * 2.3).sin() * 0.3)
+            / 1.9
+    };
+    wind.gust = Vec3::new(noise(0.), noise(2.1) * 0.5, noise(4.7)) * wind.turbulence;
+}
+
+pub fn update_flight_data(
+    time: Res<Time>,
+    wind: Res<Wind>,
+    mut aircraft: Query<(&LinearVelocity, &GlobalTransform, &mut FlightData), With<Aircraft>>,
+) {
+    let dt = time.delta_secs();
+    for (velocity, transform, mut data) in &mut aircraft {
+        let air_velocity = velocity.0 - wind.at(transform.translation().y);
+        let local = transform.rotation().inverse() * air_velocity;
+        let airspeed = air_velocity.length();
+
+        data.air_velocity = air_velocity;
+        data.airspeed = airspeed;
+        data.angle_of_attack = (-local.y).atan2(-local.z);
+        data.sideslip = if airspeed > 0.1 {
+            (local.x / airspeed).asin()
+        } else {
+            0.
+        };
+        data.dynamic_pressure = 0.5 * air_density(transform.translation().y) * airspeed * airspeed;
+        data.vertical_speed = velocity.0.y;
+
+        if dt > 0. {
+            data.acceleration = (velocity.0 - data.last_velocity) / dt;
+        }
+        data.last_velocity = velocity.0;
+        data.g_load = (data.acceleration + Vec3::Y * 9.81).dot(*transform.up()) / 9.81;
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Engine {
+    // Cleared by the fuel system when the engine runs out of fuel.
+    pub running: bool,
+    pub rpm: f32,
+    // kg/s
+    pub fuel_flow: f32,
+}
+
+impl Engine {
+    pub fn new(definition: &EngineDefinition) -> Self {
+        Self {
+            running: true,
+            rpm: definition.idle_rpm,
+            fuel_flow: 0.,
+        }
+    }
+
+    // How much of the maximum thrust the engine is producing right now.
+    pub fn thrust_fraction(&self, definition: &EngineDefinition) -> f32 {
+        ((self.rpm - definition.idle_rpm) / (definition.max_rpm - definition.idle_rpm))
+            .clamp(0., 1.)
+    }
+}
+
+pub fn update_engine(
+    time: Res<Time>,
+    input: Res<InputAxis>,
+    definition: Res<AircraftDefinition>,
+    mut engines: Query<&mut Engine, With<Aircraft>>,
+) {
+    let engine_definition = &definition.engine;
+    for mut engine in &mut engines {
+        let target_rpm = if engine.running {
+            engine_definition
+                .idle_rpm
+                .lerp(engine_definition.max_rpm, input.throttle)
+        } else {
+            0.
+        };
+        let max_change = engine_definition.spool_rate * time.delta_secs();
+        engine.rpm += (target_rpm - engine.rpm).clamp(-max_change, max_change);
+
+        engine.fuel_flow = if engine.running {
+            engine_definition
+                .idle_fuel_flow
+                .lerp(engine_definition.max_fuel_flow, input.throttle)
+        } else {
+            0.
+        };
+    }
+}
+
 pub fn aircraft_mechanics(
-    transform: Single<&GlobalTransform, With<Aircraft>>,
-    mut query: Query<Forces, With<Aircraft>>,
+    mut query: Query<
+        (Forces, &GlobalTransform, &Damage, &Engine, &FlightData),
+        (With<Aircraft>, Without<Crashed>),
+    >,
     input: Res<InputAxis>,
+    definition: Res<AircraftDefinition>,
 ) {
-    let force = transform.up() * 35000. * (input.throttle);
-    let torque = Vec3::new(input.pitch, input.yaw * 2.5, input.roll);
+    for (mut forces, transform, damage, engine, data) in &mut query {
+        let thrust = transform.forward()
+            * definition.engine.max_thrust
+            * engine.thrust_fraction(&definition.engine)
+            * damage.thrust_factor();
+
+        // Drag against the air's movement, not the ground's, so wind and gusts move the aircraft.
+        let rotation = transform.rotation();
+        let qs = data.dynamic_pressure * definition.wing_area;
+        let airflow = data.air_velocity.normalize_or_zero();
+        let drag = rotation * (-(rotation.inverse() * airflow) * definition.airframe_drag) * qs;
+
+        // Lift is perpendicular to the airflow, in the aircraft's plane of symmetry, and drags
+        // the more the wing has to lift.
+        let lift_direction = transform.right().cross(airflow).normalize_or_zero();
+        let lift_coefficient =
+            definition.lift_coefficient(data.angle_of_attack) * damage.lift_factor();
+        let lift = (lift_direction * lift_coefficient
+            - airflow * definition.induced_drag * lift_coefficient * lift_coefficient)
+            * qs;
+
+        forces.apply_force(thrust + drag + lift);
 
-    for mut forces in &mut query {
-        forces.apply_force(force);
-        forces.apply_local_torque(torque * 1200.);
+        // Local torques around x (pitching up), y (yawing left) and z (rolling left).
+        let qsc = qs * definition.mean_chord;
+        let controls = Vec3::new(input.pitch, input.yaw, input.roll)
+            * definition.control_power
+            * damage.control_factor();
+        let stability = Vec3::new(
+            -definition.pitch_stability * data.angle_of_attack.sin(),
+            -definition.yaw_stability * data.sideslip,
+            0.,
+        );
+        let rates = rotation.inverse() * forces.angular_velocity();
+        let damping = -rates * definition.rotation_damping * definition.mean_chord
+            / (2. * data.airspeed.max(1.));
+        forces.apply_local_torque((controls + stability + damping) * qsc);
     }
 }
@@ -0,0 +1,198 @@
+/*
+Animation controller for the aircraft's glTF clips.
+
+Once the aircraft's scene is ready, every named clip in its glTF file gets a node in one animation
+graph. The `animations` bindings in the aircraft definition decide what drives them: propeller
+clips loop at a speed following the engine's RPM, control surface clips are scrubbed to the
+control's deflection, and gear, canopy and door clips play forward or backward when their state
+changes. Clips that aren't bound to anything stay still.
+*/
+
+use crate::{
+    Aircraft, InputAxis,
+    aircraft_definition::{AircraftDefinition, AnimatedState, ControlAxis},
+    aircraft_mechanics::Engine,
+    doors::Doors,
+    gear::LandingGear,
+    high_lift::HighLiftDevices,
+};
+use bevy::{gltf::Gltf, platform::collections::HashMap, prelude::*, scene::SceneInstanceReady};
+
+// On the aircraft's visual scene, the file its clips come from.
+#[derive(Component)]
+pub struct AircraftModel {
+    pub gltf: Handle<Gltf>,
+}
+
+#[derive(Clone)]
+enum Driver {
+    Propeller,
+    Surface(ControlAxis),
+    // `on` is the state the clip was last played for.
+    State {
+        state: AnimatedState,
+        on: Option<bool>,
+    },
+}
+
+#[derive(Clone)]
+struct Binding {
+    node: AnimationNodeIndex,
+    duration: f32,
+    driver: Driver,
+}
+
+// On the entity with the `AnimationPlayer`.
+#[derive(Component)]
+pub struct AnimationController {
+    // Graph node of every named clip in the file.
+    pub clips: HashMap<String, AnimationNodeIndex>,
+    bindings: Vec<Binding>,
+}
+
+pub fn setup_animation_controller(
+    scene_ready: On<SceneInstanceReady>,
+    mut commands: Commands,
+    children: Query<&Children>,
+    models: Query<&AircraftModel>,
+    players: Query<(), With<AnimationPlayer>>,
+    gltfs: Res<Assets<Gltf>>,
+    animation_clips: Res<Assets<AnimationClip>>,
+    mut graphs: ResMut<Assets<AnimationGraph>>,
+    definition: Res<AircraftDefinition>,
+) {
+    let Ok(model) = models.get(scene_ready.entity) else {
+        return;
+    };
+    // The scene being ready means the whole glTF file has been loaded.
+    let Some(gltf) = gltfs.get(&model.gltf) else {
+        return;
+    };
+
+    let mut graph = AnimationGraph::new();
+    let mut clips = HashMap::default();
+    for (name, clip) in &gltf.named_animations {
+        let node = graph.add_clip(clip.clone(), 1., graph.root);
+        clips.insert(name.to_string(), node);
+    }
+
+    let animations = &definition.animations;
+    let drivers = animations
+        .propeller
+        .iter()
+        .map(|clip| (clip, Driver::Propeller))
+        .chain(
+            animations
+                .surfaces
+                .iter()
+                .map(|surface| (&surface.clip, Driver::Surface(surface.axis))),
+        )
+        .chain(animations.states.iter().map(|state| {
+            let driver = Driver::State {
+                state: state.state,
+                on: None,
+            };
+            (&state.clip, driver)
+        }));
+
+    let mut bindings = Vec::new();
+    let mut missing = Vec::new();
+    for (name, driver) in drivers {
+        let Some(clip) = gltf.named_animations.get(name.as_str()) else {
+            missing.push(name.as_str());
+            continue;
+        };
+        bindings.push(Binding {
+            node: clips[name],
+            duration: animation_clips
+                .get(clip)
+                .map_or(0., AnimationClip::duration),
+            driver,
+        });
+    }
+    if !missing.is_empty() {
+        warn!(
+            "Animation clips not found in {}: {}",
+            definition.model,
+            missing.join(", ")
+        );
+    }
+
+    let graph = graphs.add(graph);
+    for child in children.iter_descendants(scene_ready.entity) {
+        if players.contains(child) {
+            commands.entity(child).insert((
+                AnimationGraphHandle(graph.clone()),
+                AnimationController {
+                    clips: clips.clone(),
+                    bindings: bindings.clone(),
+                },
+            ));
+        }
+    }
+}
+
+pub fn drive_animations(
+    input: Res<InputAxis>,
+    definition: Res<AircraftDefinition>,
+    aircraft: Single<(&Engine, &HighLiftDevices, &LandingGear, &Doors), With<Aircraft>>,
+    mut players: Query<(&mut AnimationPlayer, &mut AnimationController)>,
+) {
+    let (engine, devices, gear, doors) = *aircraft;
+    let max_flap_angle = definition.flaps.detents.iter().copied().fold(0., f32::max);
+
+    for (mut player, mut controller) in &mut players {
+        for binding in &mut controller.bindings {
+            match &mut binding.driver {
+                Driver::Propeller => {
+                    let speed = engine.rpm / definition.animations.propeller_rpm.max(1.);
+                    player.play(binding.node).repeat().set_speed(speed);
+                }
+                Driver::Surface(axis) => {
+                    let position = match axis {
+                        ControlAxis::Pitch => (input.pitch + 1.) / 2.,
+                        ControlAxis::Roll => (input.roll + 1.) / 2.,
+                        ControlAxis::Yaw => (input.yaw + 1.) / 2.,
+                        ControlAxis::Flaps if max_flap_angle > 0. => {
+                            devices.flap_angle / max_flap_angle
+                        }
+                        ControlAxis::Flaps => 0.,
+                    };
+                    player
+                        .play(binding.node)
+                        .pause()
+                        .seek_to(position.clamp(0., 1.) * binding.duration);
+                }
+                Driver::State { state, on } => {
+                    let now = match state {
+                        AnimatedState::GearDown => gear.down_selected,
+                        AnimatedState::CanopyOpen => doors.canopy_open,
+                        AnimatedState::DoorOpen => doors.door_open,
+                        AnimatedState::SpeedBrake => devices.speed_brake_selected,
+                        AnimatedState::Spoilers => devices.spoilers_selected,
+                    };
+                    if *on == Some(now) {
+                        continue;
+                    }
+
+                    let animation = player.play(binding.node);
+                    if on.is_none() {
+                        // Start out in the current state instead of animating into it.
+                        animation
+                            .pause()
+                            .seek_to(if now { binding.duration } else { 0. });
+                    } else {
+                        // Turn around wherever the clip currently is.
+                        let time = animation.seek_time().clamp(0., binding.duration);
+                        animation.replay();
+                        animation
+                            .seek_to(time)
+                            .set_speed(if now { 1. } else { -1. })
+                            .resume();
+                    }
+                    *on = Some(now);
+                }
+            }
+        }
+    }
+}
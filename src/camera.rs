@@ -1,10 +1,38 @@
-use crate::{FollowCamera, input::Keymap};
+use crate::{
+    Aircraft, FlightSimSystems, FollowCamera,
+    floating_origin::FloatingOrigin,
+    input::Keymap,
+    replay::follow_camera_active,
+    water::{WaterBody, WaterWaves, water_level},
+};
 use bevy::{
+    camera::Exposure,
+    core_pipeline::tonemapping::Tonemapping,
     input::mouse::{AccumulatedMouseMotion, MouseScrollUnit, MouseWheel},
+    light::AtmosphereEnvironmentMapLight,
+    pbr::{Atmosphere, AtmosphereSettings},
     prelude::*,
+    render::view::Hdr,
 };
 use std::{f32::consts::FRAC_PI_2, ops::Range};
 
+// The follow camera, spawned with the aircraft and moved after the flight model.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CameraSettings::default())
+            .add_observer(spawn_follow_camera)
+            .add_systems(
+                Update,
+                (camera_controller, keep_camera_above_water)
+                    .chain()
+                    .run_if(follow_camera_active)
+                    .in_set(FlightSimSystems::Camera),
+            );
+    }
+}
+
 #[derive(Debug, Resource)]
 pub struct CameraSettings {
     pub orbit_distance: f32,
@@ -47,8 +75,35 @@ impl Default for CameraSettings {
     }
 }
 
+// Anti-aliasing, bloom, ssr, motion blur, the sky's quality and the view distance are added by the
+// graphics settings.
+fn spawn_follow_camera(
+    add: On<Add, Aircraft>,
+    mut commands: Commands,
+    camera_settings: Res<CameraSettings>,
+) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_translation(camera_settings.follow_default_position)
+            .looking_at(camera_settings.follow_default_lookat, Vec3::Y),
+        Atmosphere::EARTH,
+        AtmosphereSettings::default(),
+        // Sky reflections, mostly for the water when there's no ssr.
+        AtmosphereEnvironmentMapLight::default(),
+        Exposure::SUNLIGHT,
+        Tonemapping::AgX,
+        Projection::from(PerspectiveProjection {
+            fov: 50.0_f32.to_radians(),
+            ..default()
+        }),
+        Hdr,
+        FollowCamera,
+        ChildOf(add.entity),
+    ));
+}
+
 pub fn camera_controller(
-    mut camera: Single<&mut Transform, With<Camera>>,
+    mut camera: Single<&mut Transform, With<FollowCamera>>,
     camera_settings: Res<CameraSettings>,
     mouse_buttons: Res<ButtonInput<MouseButton>>,
     mouse_motion: Res<AccumulatedMouseMotion>,
@@ -112,3 +167,26 @@ pub fn camera_controller(
 
     perspective.fov = perspective.fov.clamp(0.1, std::f32::consts::FRAC_PI_2);
 }
+
+// The orbiting camera would otherwise dip below the waves when the aircraft floats.
+fn keep_camera_above_water(
+    time: Res<Time>,
+    waves: Res<WaterWaves>,
+    origin: Res<FloatingOrigin>,
+    water: Query<(&WaterBody, &GlobalTransform)>,
+    aircraft: Single<&Transform, (With<Aircraft>, Without<FollowCamera>)>,
+    mut camera: Single<&mut Transform, With<FollowCamera>>,
+) {
+    let world = aircraft.transform_point(camera.translation);
+    let Some(level) = water_level(world, time.elapsed_secs_wrapped(), &water, &waves, &origin)
+    else {
+        return;
+    };
+    let min_height = level + 0.5;
+    if world.y < min_height {
+        camera.translation = aircraft
+            .compute_affine()
+            .inverse()
+            .transform_point3(world.with_y(min_height));
+    }
+}
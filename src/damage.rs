@@ -0,0 +1,312 @@
+/*
+Crash detection and damage.
+
+Every time the aircraft starts touching something, the contact is classified by which part hit
+(see `parts` in the aircraft definition), the sink rate just before the contact and the impulse
+the physics needed to resolve it. Depending on that it's a touchdown, a hard landing, a gear
+collapse or a crash.
+
+Damage is tracked per component, 1 being intact and 0 being destroyed, and is used by
+aircraft_mechanics.rs to weaken the aircraft. After a crash the aircraft stays put until it's
+respawned.
+*/
+
+use crate::{
+    Aircraft,
+    aircraft_definition::{AircraftDefinition, ImpactLimits, Part},
+    floating_origin::FloatingOrigin,
+    fuel::FuelTanks,
+    gear::LandingGear,
+    geodesy::{GeoReference, LastSpawnPoint},
+    input::Keymap,
+    preflight::LoadingPlan,
+    water::WaterImpact,
+    weight_and_balance::Payload,
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+#[derive(Component, Debug)]
+pub struct Damage {
+    pub wings: f32,
+    pub engine: f32,
+    pub gear: f32,
+    pub control_surfaces: f32,
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Self {
+            wings: 1.,
+            engine: 1.,
+            gear: 1.,
+            control_surfaces: 1.,
+        }
+    }
+}
+
+impl Damage {
+    fn damage(&mut self, part: Part, amount: f32) {
+        let health = match part {
+            Part::Wings => &mut self.wings,
+            Part::Engine => &mut self.engine,
+            Part::Gear => &mut self.gear,
+            Part::ControlSurfaces => &mut self.control_surfaces,
+            // The fuselage has nothing that could stop working.
+            Part::Fuselage => return,
+        };
+        *health = (*health - amount).max(0.);
+    }
+
+    pub fn thrust_factor(&self) -> f32 {
+        self.engine
+    }
+
+    // Even a wrecked wing keeps some lift.
+    pub fn lift_factor(&self) -> f32 {
+        0.5 + 0.5 * self.wings
+    }
+
+    pub fn control_factor(&self) -> f32 {
+        self.control_surfaces
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Crashed {
+    pub reason: String,
+}
+
+// Velocity of the aircraft before the last physics step, since the contact has already slowed it
+// down by the time the impact gets classified.
+#[derive(Component, Default)]
+pub struct PreviousVelocity(pub Vec3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImpactKind {
+    Touchdown,
+    HardLanding,
+    GearCollapse,
+    Crash,
+}
+
+#[derive(Message, Debug, Clone)]
+pub struct Impact {
+    pub kind: ImpactKind,
+    pub part: Part,
+    pub sink_rate: f32,
+    pub impulse: f32,
+    // The gear region hit while the gear wasn't down and locked.
+    pub gear_up: bool,
+}
+
+fn classify(part: Part, sink_rate: f32, impulse: f32, limits: &ImpactLimits) -> ImpactKind {
+    match part {
+        Part::Gear if sink_rate <= limits.touchdown_sink_rate => ImpactKind::Touchdown,
+        Part::Gear if sink_rate <= limits.hard_landing_sink_rate => ImpactKind::HardLanding,
+        Part::Gear if sink_rate <= limits.gear_collapse_sink_rate => ImpactKind::GearCollapse,
+        Part::Gear => ImpactKind::Crash,
+        _ if impulse > limits.crash_impulse || sink_rate > limits.hard_landing_sink_rate => {
+            ImpactKind::Crash
+        }
+        // Scraping a wingtip or the tail.
+        _ => ImpactKind::HardLanding,
+    }
+}
+
+// Collision events are only sent for colliders that ask for them, and the aircraft's colliders
+// are created by `ColliderConstructorHierarchy` after it's spawned.
+pub fn enable_aircraft_collision_events(
+    add: On<Add, ColliderOf>,
+    mut commands: Commands,
+    colliders: Query<&ColliderOf>,
+    aircraft: Query<(), With<Aircraft>>,
+) {
+    let Ok(collider_of) = colliders.get(add.entity) else {
+        return;
+    };
+    if aircraft.contains(collider_of.body) {
+        commands.entity(add.entity).insert(CollisionEventsEnabled);
+    }
+}
+
+pub fn detect_impacts(
+    mut collision_events: MessageReader<CollisionStart>,
+    collisions: Collisions,
+    definition: Res<AircraftDefinition>,
+    sensors: Query<(), With<Sensor>>,
+    aircraft: Query<
+        (&GlobalTransform, &PreviousVelocity, &LandingGear),
+        (With<Aircraft>, Without<Crashed>),
+    >,
+    mut impacts: MessageWriter<Impact>,
+) {
+    for event in collision_events.read() {
+        // Flying through a sensor (like a mission gate) doesn't hurt.
+        if sensors.contains(event.collider1) || sensors.contains(event.collider2) {
+            continue;
+        }
+        let Some((transform, velocity, gear)) = [event.body1, event.body2]
+            .into_iter()
+            .flatten()
+            .find_map(|body| aircraft.get(body).ok())
+        else {
+            continue;
+        };
+
+        // The strongest contact point decides which part hit.
+        let (impulse, part) = collisions
+            .get(event.collider1, event.collider2)
+            .map(|pair| {
+                let points = pair.manifolds.iter().flat_map(|manifold| &manifold.points);
+                let impulse = points
+                    .clone()
+                    .map(|point| point.normal_impulse)
+                    .sum::<f32>();
+                let part = points
+                    .max_by(|a, b| a.normal_impulse.total_cmp(&b.normal_impulse))
+                    .map_or(Part::Fuselage, |point| {
+                        let local = transform.affine().inverse().transform_point3(point.point);
+                        definition.part_at(local)
+                    });
+                (impulse, part)
+            })
+            .unwrap_or((0., Part::Fuselage));
+
+        // Without the gear out, it's the belly that touches down.
+        let gear_up = part == Part::Gear && !gear.down_and_locked();
+        let part = if gear_up { Part::Fuselage } else { part };
+        if gear_up {
+            warn!("Landed with the gear up");
+        }
+
+        let sink_rate = -velocity.0.y;
+        let impact = Impact {
+            kind: classify(part, sink_rate, impulse, &definition.impact_limits),
+            part,
+            sink_rate,
+            impulse,
+            gear_up,
+        };
+        info!("Impact: {:?}", impact);
+        impacts.write(impact);
+    }
+}
+
+pub fn apply_impacts(
+    mut commands: Commands,
+    mut impacts: MessageReader<Impact>,
+    mut water_impacts: MessageReader<WaterImpact>,
+    mut aircraft: Query<(Entity, &mut Damage), (With<Aircraft>, Without<Crashed>)>,
+) {
+    let Ok((entity, mut damage)) = aircraft.single_mut() else {
+        // Whatever happens after a crash doesn't count.
+        impacts.clear();
+        water_impacts.clear();
+        return;
+    };
+
+    for impact in impacts.read() {
+        match impact.kind {
+            ImpactKind::Touchdown => {}
+            // Scraping along the belly takes the propeller with it.
+            ImpactKind::HardLanding if impact.gear_up => damage.damage(Part::Engine, 0.5),
+            ImpactKind::HardLanding => damage.damage(impact.part, 0.25),
+            ImpactKind::GearCollapse => {
+                damage.damage(Part::Gear, 1.);
+                damage.damage(Part::Wings, 0.2);
+            }
+            ImpactKind::Crash => {
+                damage.damage(impact.part, 1.);
+                damage.damage(Part::Engine, 0.5);
+                commands.entity(entity).insert(Crashed {
+                    reason: format!(
+                        "Crashed into the ground ({:?}, {:.1} m/s sink rate)",
+                        impact.part, impact.sink_rate
+                    ),
+                });
+            }
+        }
+    }
+
+    for impact in water_impacts.read() {
+        let reason = match impact {
+            WaterImpact::Landed => continue,
+            WaterImpact::Ditched { sink_rate } => {
+                format!("Ditched in the water ({sink_rate:.1} m/s sink rate)")
+            }
+            WaterImpact::Crashed { sink_rate } => {
+                damage.damage(Part::Wings, 1.);
+                damage.damage(Part::Engine, 1.);
+                format!("Crashed into the water ({sink_rate:.1} m/s sink rate)")
+            }
+        };
+        commands.entity(entity).insert(Crashed { reason });
+    }
+}
+
+pub fn track_previous_velocity(
+    mut aircraft: Query<(&LinearVelocity, &mut PreviousVelocity), With<Aircraft>>,
+) {
+    for (velocity, mut previous) in &mut aircraft {
+        previous.0 = velocity.0;
+    }
+}
+
+pub fn respawn(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    spawn: Res<LastSpawnPoint>,
+    plan: Res<LoadingPlan>,
+    reference: Res<GeoReference>,
+    origin: Res<FloatingOrigin>,
+    mut aircraft: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Position,
+            &mut Rotation,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+            &mut Damage,
+            &mut FuelTanks,
+            &mut Payload,
+            &mut LandingGear,
+        ),
+        With<Aircraft>,
+    >,
+) {
+    if !keyboard_input.just_pressed(keymap.respawn) {
+        return;
+    }
+
+    let target = spawn.0.transform(&reference, &origin);
+    for (
+        entity,
+        mut transform,
+        mut position,
+        mut rotation,
+        mut linear,
+        mut angular,
+        mut damage,
+        mut tanks,
+        mut payload,
+        mut gear,
+    ) in &mut aircraft
+    {
+        *transform = target;
+        position.0 = target.translation;
+        rotation.0 = target.rotation;
+        linear.0 = Vec3::ZERO;
+        angular.0 = Vec3::ZERO;
+        *damage = Damage::default();
+        *tanks = plan.fuel.clone();
+        *payload = plan.payload.clone();
+        *gear = LandingGear::default();
+        commands
+            .entity(entity)
+            .remove::<Crashed>()
+            .insert(PreviousVelocity::default());
+    }
+}
@@ -0,0 +1,38 @@
+/*
+Canopy and door.
+
+They can only be opened or closed while the aircraft is (almost) standing still, anything else
+would rip them off.
+*/
+
+use crate::{Aircraft, aircraft_mechanics::FlightData, input::ControlActions};
+use bevy::prelude::*;
+
+const MAX_OPERATING_SPEED: f32 = 10.;
+
+#[derive(Component, Debug, Default)]
+pub struct Doors {
+    pub canopy_open: bool,
+    pub door_open: bool,
+}
+
+pub fn operate_doors(
+    actions: Res<ControlActions>,
+    mut aircraft: Query<(&mut Doors, &FlightData), With<Aircraft>>,
+) {
+    for (mut doors, data) in &mut aircraft {
+        if !actions.toggle_canopy && !actions.toggle_door {
+            continue;
+        }
+        if data.airspeed > MAX_OPERATING_SPEED {
+            info!("Too fast to operate the canopy or door");
+            continue;
+        }
+        if actions.toggle_canopy {
+            doors.canopy_open = !doors.canopy_open;
+        }
+        if actions.toggle_door {
+            doors.door_open = !doors.door_open;
+        }
+    }
+}
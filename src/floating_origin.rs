@@ -0,0 +1,77 @@
+/*
+Floating origin.
+
+Transforms are f32, which gets imprecise far away from the origin and makes physics and rendering
+jitter after a few tens of kilometers. To avoid that, the whole world is shifted back whenever the
+aircraft gets further than `rebase_distance` away from the origin, so it always stays close to it.
+
+The world is only ever shifted horizontally and by whole grid cells. `FloatingOrigin::cell` keeps
+track of the total shift, so absolute positions can be recovered in f64 with `absolute`.
+*/
+
+use crate::Aircraft;
+use avian3d::prelude::*;
+use bevy::{math::DVec3, prelude::*};
+
+#[derive(Resource, Debug)]
+pub struct FloatingOrigin {
+    // The grid cell (along x and z) the world origin is currently in.
+    pub cell: IVec2,
+    pub cell_size: f32,
+    pub rebase_distance: f32,
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        Self {
+            cell: IVec2::ZERO,
+            cell_size: 1000.,
+            rebase_distance: 5000.,
+        }
+    }
+}
+
+impl FloatingOrigin {
+    // Absolute position of the current world origin.
+    pub fn offset(&self) -> DVec3 {
+        let cell_size = self.cell_size as f64;
+        DVec3::new(
+            self.cell.x as f64 * cell_size,
+            0.,
+            self.cell.y as f64 * cell_size,
+        )
+    }
+
+    pub fn absolute(&self, local: Vec3) -> DVec3 {
+        self.offset() + local.as_dvec3()
+    }
+
+    pub fn local(&self, absolute: DVec3) -> Vec3 {
+        (absolute - self.offset()).as_vec3()
+    }
+}
+
+// Shifts every top-level entity (children move along with their parents) once the aircraft is
+// too far from the origin.
+pub fn rebase_origin(
+    mut origin: ResMut<FloatingOrigin>,
+    aircraft: Single<&Transform, With<Aircraft>>,
+    mut roots: Query<(&mut Transform, Option<&mut Position>), Without<ChildOf>>,
+) {
+    let position = aircraft.translation;
+    if position.xz().length() < origin.rebase_distance {
+        return;
+    }
+
+    let cells = (position.xz() / origin.cell_size).round().as_ivec2();
+    let shift = Vec3::new(cells.x as f32, 0., cells.y as f32) * origin.cell_size;
+    origin.cell += cells;
+    info!("Rebasing floating origin to cell {}", origin.cell);
+
+    for (mut transform, body_position) in &mut roots {
+        transform.translation -= shift;
+        if let Some(mut body_position) = body_position {
+            body_position.0 -= shift;
+        }
+    }
+}
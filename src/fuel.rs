@@ -0,0 +1,70 @@
+/*
+Fuel system.
+
+Every tank in the aircraft definition has a level in `FuelTanks`. The engine burns fuel from the
+tanks with the lowest `feed_priority` that still have fuel in them, split evenly between them.
+Once every tank is empty the engine starves and stops, and it starts again as soon as there's fuel.
+*/
+
+use crate::{Aircraft, aircraft_definition::AircraftDefinition, aircraft_mechanics::Engine};
+use bevy::prelude::*;
+
+// Fuel in kg, in the same order as the tanks in the aircraft definition.
+#[derive(Component, Debug, Clone)]
+pub struct FuelTanks(pub Vec<f32>);
+
+impl FuelTanks {
+    pub fn full(definition: &AircraftDefinition) -> Self {
+        Self(definition.tanks.iter().map(|tank| tank.capacity).collect())
+    }
+
+    pub fn total(&self) -> f32 {
+        self.0.iter().sum()
+    }
+
+    // Takes `amount` kg out of the feeding tanks, returns false if there wasn't enough.
+    fn draw(&mut self, definition: &AircraftDefinition, mut amount: f32) -> bool {
+        // Stop at a milligram, so rounding errors can't keep this going forever.
+        while amount > 1e-6 {
+            let Some(priority) = definition
+                .tanks
+                .iter()
+                .zip(&self.0)
+                .filter(|(_, level)| **level > 0.)
+                .map(|(tank, _)| tank.feed_priority)
+                .min()
+            else {
+                return false;
+            };
+
+            let feeding: Vec<usize> = (0..self.0.len())
+                .filter(|&i| definition.tanks[i].feed_priority == priority && self.0[i] > 0.)
+                .collect();
+            let share = amount / feeding.len() as f32;
+            for i in feeding {
+                let taken = share.min(self.0[i]);
+                self.0[i] -= taken;
+                amount -= taken;
+            }
+        }
+        true
+    }
+}
+
+pub fn consume_fuel(
+    time: Res<Time>,
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<(&mut FuelTanks, &mut Engine), With<Aircraft>>,
+) {
+    for (mut tanks, mut engine) in &mut aircraft {
+        if engine.running {
+            if !tanks.draw(&definition, engine.fuel_flow * time.delta_secs()) {
+                warn!("Engine starved of fuel");
+                engine.running = false;
+            }
+        } else if tanks.total() > 0. {
+            info!("Engine restarted");
+            engine.running = true;
+        }
+    }
+}
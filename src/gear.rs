@@ -0,0 +1,131 @@
+/*
+Retractable landing gear.
+
+The gear takes `transition_time` seconds to travel and adds drag while it's out. It can't be
+retracted while the aircraft's weight is on the wheels, which is the case whenever the gear region
+(see `parts` in the aircraft definition) touches something while it's down and locked. The
+aircraft rolls on the gear while it's down and locked, and slides on its belly otherwise. A damaged
+gear rolls worse, and a destroyed one collapses and can't be lowered until the aircraft respawns.
+*/
+
+use crate::{
+    Aircraft,
+    aircraft_definition::{AircraftDefinition, Part},
+    aircraft_mechanics::FlightData,
+    damage::{Crashed, Damage},
+    input::ControlActions,
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+// Rolling on the wheels barely slows the aircraft down, sliding on anything else does.
+const ROLLING_FRICTION: f32 = 0.03;
+const SLIDING_FRICTION: f32 = 0.5;
+
+#[derive(Component, Debug)]
+pub struct LandingGear {
+    // 0 is retracted, 1 down and locked.
+    pub position: f32,
+    pub down_selected: bool,
+    pub weight_on_wheels: bool,
+}
+
+impl Default for LandingGear {
+    fn default() -> Self {
+        Self {
+            position: 1.,
+            down_selected: true,
+            weight_on_wheels: false,
+        }
+    }
+}
+
+impl LandingGear {
+    pub fn down_and_locked(&self) -> bool {
+        self.position >= 1.
+    }
+}
+
+pub fn detect_weight_on_wheels(
+    collisions: Collisions,
+    definition: Res<AircraftDefinition>,
+    sensors: Query<(), With<Sensor>>,
+    mut aircraft: Query<(Entity, &GlobalTransform, &mut LandingGear), With<Aircraft>>,
+) {
+    for (entity, transform, mut gear) in &mut aircraft {
+        let inverse = transform.affine().inverse();
+        gear.weight_on_wheels = gear.down_and_locked()
+            && collisions
+                .iter()
+                .filter(|pair| pair.is_touching())
+                .filter(|pair| pair.body1 == Some(entity) || pair.body2 == Some(entity))
+                .filter(|pair| {
+                    !sensors.contains(pair.collider1) && !sensors.contains(pair.collider2)
+                })
+                .flat_map(|pair| pair.manifolds.iter().flat_map(|manifold| &manifold.points))
+                .any(|point| {
+                    definition.part_at(inverse.transform_point3(point.point)) == Part::Gear
+                });
+    }
+}
+
+pub fn operate_gear(
+    time: Res<Time>,
+    actions: Res<ControlActions>,
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<(&mut LandingGear, &Damage), With<Aircraft>>,
+) {
+    for (mut gear, damage) in &mut aircraft {
+        if damage.gear <= 0. {
+            if actions.toggle_gear {
+                info!("Gear destroyed, it can't be lowered");
+            }
+            gear.position = 0.;
+            continue;
+        }
+
+        if actions.toggle_gear && definition.gear.retractable {
+            if gear.down_selected && gear.weight_on_wheels {
+                info!("Gear retraction blocked, weight on wheels");
+            } else {
+                gear.down_selected = !gear.down_selected;
+            }
+        }
+
+        let target = if gear.down_selected { 1. } else { 0. };
+        let max_change = time.delta_secs() / definition.gear.transition_time;
+        gear.position += (target - gear.position).clamp(-max_change, max_change);
+    }
+}
+
+// The aircraft's colliders have no friction of their own, so what the body has is used for every
+// contact. `Min` keeps the ground's usual friction from taking over.
+pub fn update_ground_friction(
+    mut aircraft: Query<(&LandingGear, &Damage, &mut Friction), With<Aircraft>>,
+) {
+    for (gear, damage, mut friction) in &mut aircraft {
+        let coefficient = if gear.down_and_locked() {
+            ROLLING_FRICTION.lerp(SLIDING_FRICTION, 1. - damage.gear)
+        } else {
+            SLIDING_FRICTION
+        };
+        let target = Friction::new(coefficient).with_combine_rule(CoefficientCombine::Min);
+        if *friction != target {
+            *friction = target;
+        }
+    }
+}
+
+pub fn gear_drag(
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<(Forces, &FlightData, &LandingGear), (With<Aircraft>, Without<Crashed>)>,
+) {
+    for (mut forces, data, gear) in &mut aircraft {
+        if data.airspeed < 1. {
+            continue;
+        }
+        let airflow = data.air_velocity.normalize();
+        let drag = data.dynamic_pressure * definition.wing_area * definition.gear.drag;
+        forces.apply_force(-airflow * drag * gear.position);
+    }
+}
@@ -0,0 +1,191 @@
+/*
+Geodetic coordinates.
+
+The world is treated as a flat ENU (east, north, up) tangent plane touching the WGS84 ellipsoid at
+`GeoReference`. In world space, east is +X, up is +Y and north is -Z.
+Everything here works on absolute positions, see floating_origin.rs for converting them from and to
+local transforms.
+*/
+
+use crate::{Aircraft, floating_origin::FloatingOrigin};
+use bevy::{math::DVec3, prelude::*};
+use serde::{Deserialize, Serialize};
+
+// WGS84 ellipsoid
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_223_563;
+const ECCENTRICITY_SQUARED: f64 = FLATTENING * (2.0 - FLATTENING);
+
+// Latitude and longitude in degrees, altitude in meters above the ellipsoid.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Geodetic {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl Geodetic {
+    pub fn to_ecef(self) -> DVec3 {
+        let (lat, lon) = (self.latitude.to_radians(), self.longitude.to_radians());
+        let n = prime_vertical_radius(lat);
+        DVec3::new(
+            (n + self.altitude) * lat.cos() * lon.cos(),
+            (n + self.altitude) * lat.cos() * lon.sin(),
+            (n * (1.0 - ECCENTRICITY_SQUARED) + self.altitude) * lat.sin(),
+        )
+    }
+
+    pub fn from_ecef(ecef: DVec3) -> Self {
+        let p = (ecef.x * ecef.x + ecef.y * ecef.y).sqrt();
+        let lon = ecef.y.atan2(ecef.x);
+        let mut lat = ecef.z.atan2(p * (1.0 - ECCENTRICITY_SQUARED));
+        let mut alt = 0.0;
+        // Converges to well below a millimeter in a few iterations.
+        for _ in 0..5 {
+            let n = prime_vertical_radius(lat);
+            alt = p / lat.cos() - n;
+            lat = ecef
+                .z
+                .atan2(p * (1.0 - ECCENTRICITY_SQUARED * n / (n + alt)));
+        }
+        Self {
+            latitude: lat.to_degrees(),
+            longitude: lon.to_degrees(),
+            altitude: alt,
+        }
+    }
+
+    // Unit vectors pointing east, north and up at this location, in ECEF.
+    fn enu_axes(self) -> (DVec3, DVec3, DVec3) {
+        let (lat, lon) = (self.latitude.to_radians(), self.longitude.to_radians());
+        let east = DVec3::new(-lon.sin(), lon.cos(), 0.0);
+        let north = DVec3::new(-lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos());
+        let up = DVec3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+        (east, north, up)
+    }
+}
+
+fn prime_vertical_radius(lat: f64) -> f64 {
+    SEMI_MAJOR_AXIS / (1.0 - ECCENTRICITY_SQUARED * lat.sin().powi(2)).sqrt()
+}
+
+// The point where the world origin touches the earth.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct GeoReference {
+    pub origin: Geodetic,
+    // Degrees, positive when magnetic north is east of true north.
+    pub magnetic_declination: f64,
+}
+
+impl Default for GeoReference {
+    fn default() -> Self {
+        Self {
+            origin: Geodetic {
+                latitude: 46.9125,
+                longitude: 7.4994,
+                altitude: 0.0,
+            },
+            magnetic_declination: 3.0,
+        }
+    }
+}
+
+impl GeoReference {
+    pub fn world_to_geodetic(&self, world: DVec3) -> Geodetic {
+        let (east, north, up) = self.origin.enu_axes();
+        let ecef = self.origin.to_ecef() + east * world.x + north * -world.z + up * world.y;
+        Geodetic::from_ecef(ecef)
+    }
+
+    pub fn geodetic_to_world(&self, geodetic: Geodetic) -> DVec3 {
+        let (east, north, up) = self.origin.enu_axes();
+        let offset = geodetic.to_ecef() - self.origin.to_ecef();
+        DVec3::new(offset.dot(east), offset.dot(up), -offset.dot(north))
+    }
+
+    // Direction of true north at `geodetic`, in world space. Only differs noticeably from -Z far
+    // away from the reference point.
+    pub fn true_north(&self, geodetic: Geodetic) -> Vec3 {
+        let (east, north, up) = self.origin.enu_axes();
+        let (_, local_north, _) = geodetic.enu_axes();
+        Vec3::new(
+            local_north.dot(east) as f32,
+            local_north.dot(up) as f32,
+            -local_north.dot(north) as f32,
+        )
+    }
+
+    // True heading in degrees (0..360, clockwise from north) of a world space direction.
+    pub fn heading(&self, geodetic: Geodetic, direction: Vec3) -> f32 {
+        let north = self.true_north(geodetic).with_y(0.).normalize();
+        let east = north.cross(Vec3::Y);
+        let direction = direction.with_y(0.);
+        direction
+            .dot(east)
+            .atan2(direction.dot(north))
+            .to_degrees()
+            .rem_euclid(360.)
+    }
+}
+
+// A place to put the aircraft, `heading` is true heading in degrees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub location: Geodetic,
+    pub heading: f32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self {
+            location: Geodetic {
+                altitude: 20.0,
+                ..GeoReference::default().origin
+            },
+            heading: 0.,
+        }
+    }
+}
+
+impl SpawnPoint {
+    pub fn transform(&self, reference: &GeoReference, origin: &FloatingOrigin) -> Transform {
+        let translation = origin.local(reference.geodetic_to_world(self.location));
+        Transform::from_translation(translation)
+            .with_rotation(Quat::from_rotation_y(-self.heading.to_radians()))
+    }
+}
+
+// A spawn point to pick from in the location menu.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedSpawnPoint {
+    pub name: String,
+    #[serde(flatten)]
+    pub spawn: SpawnPoint,
+}
+
+// Where the aircraft was last put, respawning goes back there.
+#[derive(Resource, Debug, Clone)]
+pub struct LastSpawnPoint(pub SpawnPoint);
+
+// Where the aircraft currently is, for the HUD and navigation.
+#[derive(Resource, Debug, Default)]
+pub struct AircraftLocation {
+    pub geodetic: Geodetic,
+    pub heading_true: f32,
+    pub heading_magnetic: f32,
+}
+
+pub fn update_aircraft_location(
+    mut location: ResMut<AircraftLocation>,
+    reference: Res<GeoReference>,
+    origin: Res<FloatingOrigin>,
+    aircraft: Single<&Transform, With<Aircraft>>,
+) {
+    let geodetic = reference.world_to_geodetic(origin.absolute(aircraft.translation));
+    let heading_true = reference.heading(geodetic, *aircraft.forward());
+
+    location.geodetic = geodetic;
+    location.heading_true = heading_true;
+    location.heading_magnetic =
+        (heading_true - reference.magnetic_declination as f32).rem_euclid(360.);
+}
@@ -0,0 +1,440 @@
+/*
+Graphics quality.
+
+`graphics` in settings.json picks one of the presets (`Low`, `Medium`, `High`, `Ultra`), and any of
+its values can be replaced in `overrides`, e.g.
+`{ "preset": "High", "overrides": { "motion_blur_samples": 0 } }`. The result is the `Graphics`
+resource, which is applied to the flight camera and the sun whenever it changes, so anything can
+change it at runtime. F8 cycles through the presets while flying, keeping the overrides.
+
+- Shadows: on or off, how far they reach, how many cascades they're split into and the size of the
+  shadow maps.
+- Anti-aliasing: off, 4x MSAA, FXAA or TAA. The deferred renderer can't do MSAA, so with ssr it's
+  FXAA instead.
+- Bloom, and whether the sky is drawn from lookup textures or raymarched (slower, but it stays right
+  when flying high).
+- Screen space reflections need the deferred renderer, which can only be picked at startup. Turning
+  ssr on does nothing unless the game was started with it.
+- Motion blur samples, 0 turns it off.
+- Below a render scale of 1 the scene is rendered into a smaller image that's stretched over the
+  window, the HUD stays sharp.
+- The view distance is the camera's far plane, and the fog never lets you see further than that
+  (see weather.rs).
+*/
+
+use crate::{FollowCamera, GameState, Settings, input::Keymap, time_of_day::Sun};
+use bevy::{
+    anti_alias::{fxaa::Fxaa, taa::TemporalAntiAliasing},
+    asset::RenderAssetUsages,
+    camera::RenderTarget,
+    light::{CascadeShadowConfigBuilder, DirectionalLightShadowMap},
+    pbr::{
+        AtmosphereMode, AtmosphereSettings, DefaultOpaqueRendererMethod, ScreenSpaceReflections,
+    },
+    post_process::{bloom::Bloom, motion_blur::MotionBlur},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    window::{PrimaryWindow, WindowRef},
+};
+use serde::{Deserialize, Serialize};
+
+const MIN_RENDER_SCALE: f32 = 0.25;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphicsPreset {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Ultra,
+}
+
+impl GraphicsPreset {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Low => Self::Medium,
+            Self::Medium => Self::High,
+            Self::High => Self::Ultra,
+            Self::Ultra => Self::Low,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntiAliasing {
+    Off,
+    Msaa,
+    Fxaa,
+    Taa,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AtmosphereQuality {
+    Lut,
+    Raymarched,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Graphics {
+    pub preset: GraphicsPreset,
+    pub shadows: bool,
+    // Meters.
+    pub shadow_distance: f32,
+    pub shadow_cascades: usize,
+    pub shadow_map_size: usize,
+    pub anti_aliasing: AntiAliasing,
+    pub bloom: bool,
+    pub atmosphere: AtmosphereQuality,
+    pub ssr: bool,
+    // 0 turns motion blur off.
+    pub motion_blur_samples: u32,
+    // Fraction of the window's resolution the scene is rendered at.
+    pub render_scale: f32,
+    // Meters.
+    pub view_distance: f32,
+}
+
+impl Graphics {
+    pub fn preset(preset: GraphicsPreset) -> Self {
+        match preset {
+            GraphicsPreset::Low => Self {
+                preset,
+                shadows: true,
+                shadow_distance: 1000.,
+                shadow_cascades: 1,
+                shadow_map_size: 1024,
+                anti_aliasing: AntiAliasing::Fxaa,
+                bloom: false,
+                atmosphere: AtmosphereQuality::Lut,
+                ssr: false,
+                motion_blur_samples: 0,
+                render_scale: 0.75,
+                view_distance: 8000.,
+            },
+            GraphicsPreset::Medium => Self {
+                preset,
+                shadows: true,
+                shadow_distance: 2500.,
+                shadow_cascades: 2,
+                shadow_map_size: 2048,
+                anti_aliasing: AntiAliasing::Msaa,
+                bloom: true,
+                atmosphere: AtmosphereQuality::Lut,
+                ssr: false,
+                motion_blur_samples: 0,
+                render_scale: 1.,
+                view_distance: 15000.,
+            },
+            GraphicsPreset::High => Self {
+                preset,
+                shadows: true,
+                shadow_distance: 5000.,
+                shadow_cascades: 4,
+                shadow_map_size: 2048,
+                anti_aliasing: AntiAliasing::Fxaa,
+                bloom: true,
+                atmosphere: AtmosphereQuality::Raymarched,
+                ssr: true,
+                motion_blur_samples: 6,
+                render_scale: 1.,
+                view_distance: 30000.,
+            },
+            GraphicsPreset::Ultra => Self {
+                preset,
+                shadows: true,
+                shadow_distance: 8000.,
+                shadow_cascades: 4,
+                shadow_map_size: 4096,
+                anti_aliasing: AntiAliasing::Taa,
+                bloom: true,
+                atmosphere: AtmosphereQuality::Raymarched,
+                ssr: true,
+                motion_blur_samples: 12,
+                render_scale: 1.,
+                view_distance: 50000.,
+            },
+        }
+    }
+}
+
+// Anything set here replaces the preset's value.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct GraphicsOverrides {
+    pub shadows: Option<bool>,
+    pub shadow_distance: Option<f32>,
+    pub shadow_cascades: Option<usize>,
+    pub shadow_map_size: Option<usize>,
+    pub anti_aliasing: Option<AntiAliasing>,
+    pub bloom: Option<bool>,
+    pub atmosphere: Option<AtmosphereQuality>,
+    pub ssr: Option<bool>,
+    pub motion_blur_samples: Option<u32>,
+    pub render_scale: Option<f32>,
+    pub view_distance: Option<f32>,
+}
+
+impl GraphicsOverrides {
+    pub fn apply(&self, mut graphics: Graphics) -> Graphics {
+        graphics.shadows = self.shadows.unwrap_or(graphics.shadows);
+        // The cascades need some distance to split, `shadows: false` turns them off.
+        graphics.shadow_distance = self
+            .shadow_distance
+            .unwrap_or(graphics.shadow_distance)
+            .max(1.);
+        graphics.shadow_cascades = self.shadow_cascades.unwrap_or(graphics.shadow_cascades);
+        graphics.shadow_map_size = self.shadow_map_size.unwrap_or(graphics.shadow_map_size);
+        graphics.anti_aliasing = self.anti_aliasing.unwrap_or(graphics.anti_aliasing);
+        graphics.bloom = self.bloom.unwrap_or(graphics.bloom);
+        graphics.atmosphere = self.atmosphere.unwrap_or(graphics.atmosphere);
+        graphics.ssr = self.ssr.unwrap_or(graphics.ssr);
+        graphics.motion_blur_samples = self
+            .motion_blur_samples
+            .unwrap_or(graphics.motion_blur_samples);
+        graphics.render_scale = self.render_scale.unwrap_or(graphics.render_scale);
+        graphics.view_distance = self.view_distance.unwrap_or(graphics.view_distance);
+        graphics
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub preset: GraphicsPreset,
+    pub overrides: GraphicsOverrides,
+}
+
+impl GraphicsSettings {
+    pub fn resolve(&self) -> Graphics {
+        self.overrides.apply(Graphics::preset(self.preset))
+    }
+}
+
+// Whether the game was started with the deferred renderer.
+#[derive(Resource)]
+struct DeferredRendering(bool);
+
+// The window-sized image showing the scene when it's rendered below full scale, and the camera
+// drawing it.
+#[derive(Component)]
+struct ScaledView;
+
+#[derive(Component)]
+struct ScaledViewCamera;
+
+pub struct GraphicsPlugin {
+    pub settings: GraphicsSettings,
+}
+
+impl Plugin for GraphicsPlugin {
+    fn build(&self, app: &mut App) {
+        let graphics = self.settings.resolve();
+        // Has to be set before any material is prepared.
+        if graphics.ssr {
+            app.insert_resource(DefaultOpaqueRendererMethod::deferred());
+        }
+        app.insert_resource(DeferredRendering(graphics.ssr))
+            .insert_resource(graphics)
+            .add_systems(
+                Update,
+                (
+                    cycle_graphics
+                        .run_if(in_state(GameState::InFlight).or(in_state(GameState::Paused))),
+                    apply_light_graphics,
+                    apply_camera_graphics,
+                    update_render_scale,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn cycle_graphics(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    settings: Res<Settings>,
+    mut graphics: ResMut<Graphics>,
+) {
+    if keyboard_input.just_pressed(keymap.graphics) {
+        *graphics = settings
+            .graphics
+            .overrides
+            .apply(Graphics::preset(graphics.preset.next()));
+        info!("Graphics: {:?}", graphics.preset);
+    }
+}
+
+fn apply_light_graphics(
+    mut commands: Commands,
+    graphics: Res<Graphics>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+    mut lights: Query<(Entity, &mut DirectionalLight, Ref<Sun>)>,
+) {
+    if graphics.is_changed() {
+        shadow_map.size = graphics.shadow_map_size;
+    }
+    for (entity, mut light, sun) in &mut lights {
+        if !graphics.is_changed() && !sun.is_added() {
+            continue;
+        }
+        light.shadows_enabled = graphics.shadows;
+        commands.entity(entity).insert(
+            CascadeShadowConfigBuilder {
+                num_cascades: graphics.shadow_cascades.max(1),
+                maximum_distance: graphics.shadow_distance,
+                ..default()
+            }
+            .build(),
+        );
+    }
+}
+
+fn apply_camera_graphics(
+    mut commands: Commands,
+    graphics: Res<Graphics>,
+    deferred: Res<DeferredRendering>,
+    mut cameras: Query<(
+        Entity,
+        &mut Projection,
+        &mut AtmosphereSettings,
+        Ref<FollowCamera>,
+    )>,
+) {
+    for (entity, mut projection, mut atmosphere, camera) in &mut cameras {
+        if !graphics.is_changed() && !camera.is_added() {
+            continue;
+        }
+        let mut camera = commands.entity(entity);
+
+        let anti_aliasing = match graphics.anti_aliasing {
+            AntiAliasing::Msaa if deferred.0 => AntiAliasing::Fxaa,
+            anti_aliasing => anti_aliasing,
+        };
+        camera.remove::<(Fxaa, TemporalAntiAliasing)>();
+        match anti_aliasing {
+            AntiAliasing::Off => camera.insert(Msaa::Off),
+            AntiAliasing::Msaa => camera.insert(Msaa::Sample4),
+            AntiAliasing::Fxaa => camera.insert((Msaa::Off, Fxaa::default())),
+            AntiAliasing::Taa => camera.insert((Msaa::Off, TemporalAntiAliasing::default())),
+        };
+
+        if graphics.ssr && deferred.0 {
+            camera.insert(ScreenSpaceReflections::default());
+        } else {
+            camera.remove::<ScreenSpaceReflections>();
+        }
+        if graphics.bloom {
+            camera.insert(Bloom::NATURAL);
+        } else {
+            camera.remove::<Bloom>();
+        }
+        if graphics.motion_blur_samples > 0 {
+            camera.insert(MotionBlur {
+                shutter_angle: 1.0,
+                samples: graphics.motion_blur_samples,
+            });
+        } else {
+            camera.remove::<MotionBlur>();
+        }
+
+        atmosphere.rendering_method = match graphics.atmosphere {
+            AtmosphereQuality::Lut => AtmosphereMode::LookupTexture,
+            AtmosphereQuality::Raymarched => AtmosphereMode::Raymarched,
+        };
+        if let Projection::Perspective(perspective) = &mut *projection {
+            perspective.far = graphics.view_distance;
+        }
+    }
+}
+
+fn render_target(size: Extent3d) -> Image {
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
+// Below full scale, the flight camera renders into an image that a second camera stretches over the
+// window. The UI goes to that second camera, since it's the last one drawing to the window.
+fn update_render_scale(
+    mut commands: Commands,
+    graphics: Res<Graphics>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<&mut Camera, With<FollowCamera>>,
+    views: Query<(Entity, &ImageNode), With<ScaledView>>,
+    view_cameras: Query<Entity, With<ScaledViewCamera>>,
+) {
+    if graphics.render_scale >= 1. || cameras.is_empty() {
+        for entity in views.iter().map(|(entity, _)| entity).chain(&view_cameras) {
+            commands.entity(entity).despawn();
+        }
+        for mut camera in &mut cameras {
+            if !matches!(camera.target, RenderTarget::Window(WindowRef::Primary)) {
+                camera.target = RenderTarget::default();
+            }
+        }
+        return;
+    }
+
+    let size = (window.physical_size().as_vec2() * graphics.render_scale.max(MIN_RENDER_SCALE))
+        .round()
+        .as_uvec2()
+        .max(UVec2::ONE);
+    let extent = Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    };
+    let image = match views.single() {
+        Ok((_, view)) => {
+            // `get_mut` marks the image as changed, which uploads it to the GPU again.
+            if images
+                .get(&view.image)
+                .is_some_and(|image| image.size() != size)
+                && let Some(image) = images.get_mut(&view.image)
+            {
+                image.resize(extent);
+            }
+            view.image.clone()
+        }
+        Err(_) => {
+            let image = images.add(render_target(extent));
+            commands.spawn((
+                Camera2d,
+                Camera {
+                    order: 1,
+                    ..default()
+                },
+                ScaledViewCamera,
+                DespawnOnEnter(GameState::MainMenu),
+            ));
+            commands.spawn((
+                ImageNode::new(image.clone()),
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: percent(100.0),
+                    height: percent(100.0),
+                    ..default()
+                },
+                // Behind the HUD.
+                GlobalZIndex(-1),
+                ScaledView,
+                DespawnOnEnter(GameState::MainMenu),
+            ));
+            image
+        }
+    };
+
+    for mut camera in &mut cameras {
+        if !matches!(&camera.target, RenderTarget::Image(target) if target.handle == image) {
+            camera.target = RenderTarget::Image(image.clone().into());
+        }
+    }
+}
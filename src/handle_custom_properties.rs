@@ -7,12 +7,35 @@ Thanks to Christopher Biscardi for making a tutorial about it.
 rigid_body: Static, Dynamic
 collider: TrimeshFromMesh, Cuboid
 (cube_size: Vec3, only if collider is cuboid)
+
+Runways (see landing.rs) are objects with these node properties:
+runway: String, the name
+runway_length, runway_width: f32, in meters
+
+Add `ScenePropertiesPlugin` and put a `SceneProperties` on a `SceneRoot` to get its colliders.
 */
 
+use crate::landing::Runway;
 use avian3d::prelude::*;
-use bevy::{gltf::GltfMeshExtras, prelude::*, scene::SceneInstanceReady};
+use bevy::{
+    gltf::{GltfExtras, GltfMeshExtras},
+    prelude::*,
+    scene::SceneInstanceReady,
+};
 use serde::{Deserialize, Serialize};
 
+pub struct ScenePropertiesPlugin;
+
+impl Plugin for ScenePropertiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(on_scene_spawn);
+    }
+}
+
+// Scenes whose custom properties should be turned into colliders once they're ready.
+#[derive(Component, Default)]
+pub struct SceneProperties;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BMeshExtras {
     pub collider: BCollider,
@@ -20,6 +43,13 @@ pub struct BMeshExtras {
     pub cube_size: Option<Vec3>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BRunwayExtras {
+    pub runway: String,
+    pub runway_length: f32,
+    pub runway_width: f32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum BCollider {
     TrimeshFromMesh,
@@ -32,13 +62,28 @@ pub enum BRigidBody {
     Dynamic,
 }
 
-pub fn on_scene_spawn(
+fn on_scene_spawn(
     trigger: On<SceneInstanceReady>,
     mut commands: Commands,
+    scenes: Query<(), With<SceneProperties>>,
     children: Query<&Children>,
     extras: Query<&GltfMeshExtras>,
+    node_extras: Query<&GltfExtras>,
 ) {
+    if !scenes.contains(trigger.entity) {
+        return;
+    }
     for entity in children.iter_descendants(trigger.entity.entity()) {
+        // Other node properties aren't runways, those are skipped.
+        if let Ok(gltf_extras) = node_extras.get(entity)
+            && let Ok(runway) = serde_json::from_str::<BRunwayExtras>(&gltf_extras.value)
+        {
+            commands.entity(entity).insert(Runway {
+                name: runway.runway,
+                length: runway.runway_length,
+                width: runway.runway_width,
+            });
+        }
         let Ok(gltf_mesh_extras) = extras.get(entity) else {
             continue;
         };
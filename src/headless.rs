@@ -0,0 +1,129 @@
+/*
+Headless mode, for automated flight tests.
+
+`cargo run -- --headless [seconds]` flies the aircraft picked in settings.json at full throttle
+for the given time (10 seconds by default) without a window, renderer or assets and prints its
+state every second. `headless_app` sets up the same app for tests (see tests/flight_model.rs).
+Every update advances the simulation by exactly `TIMESTEP`, so runs are reproducible.
+*/
+
+use crate::{
+    InputAxis,
+    aircraft_definition::AircraftDefinition,
+    aircraft_mechanics::Engine,
+    damage::Crashed,
+    fuel::FuelTanks,
+    preflight::LoadingPlan,
+    simulation::{FlightModelPlugin, spawn_simulated_aircraft},
+};
+use avian3d::prelude::*;
+use bevy::{
+    ecs::system::RunSystemOnce, mesh::MeshPlugin, prelude::*, scene::ScenePlugin,
+    time::TimeUpdateStrategy,
+};
+use std::time::Duration;
+
+pub const TIMESTEP: f64 = 1. / 60.;
+
+// Like `App::run` would, call `app.finish()` and `app.cleanup()` once every plugin is added, avian
+// sets up parts of the physics there.
+pub fn headless_app(definition: AircraftDefinition) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        AssetPlugin::default(),
+        MeshPlugin,
+        // avian's collider constructors look for scenes.
+        ScenePlugin,
+        PhysicsPlugins::default(),
+        FlightModelPlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        TIMESTEP,
+    )))
+    .insert_resource(Time::<Fixed>::from_seconds(TIMESTEP))
+    .insert_resource(LoadingPlan::new(&definition))
+    .insert_resource(definition);
+    app
+}
+
+// The aircraft's hitbox is part of its model, so headless aircraft get a box around all of their
+// part regions instead.
+pub fn aircraft_box(definition: &AircraftDefinition) -> Collider {
+    let (min, max) = definition
+        .parts
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), region| {
+            (min.min(region.min), max.max(region.max))
+        });
+    let size = max - min;
+    Collider::compound(vec![(
+        (min + max) / 2.,
+        Quat::IDENTITY,
+        Collider::cuboid(size.x, size.y, size.z),
+    )])
+}
+
+pub fn spawn_headless_aircraft(app: &mut App, transform: Transform) -> Entity {
+    let collider = aircraft_box(app.world().resource::<AircraftDefinition>());
+    app.world_mut()
+        .run_system_once(
+            move |mut commands: Commands,
+                  definition: Res<AircraftDefinition>,
+                  plan: Res<LoadingPlan>| {
+                let aircraft =
+                    spawn_simulated_aircraft(&mut commands, &definition, &plan, transform);
+                commands.entity(aircraft).insert(collider.clone());
+                aircraft
+            },
+        )
+        .unwrap()
+}
+
+pub fn spawn_ground(app: &mut App) -> Entity {
+    app.world_mut()
+        .spawn((
+            RigidBody::Static,
+            Collider::half_space(Vec3::Y),
+            Transform::default(),
+        ))
+        .id()
+}
+
+pub fn run_headless(definition: AircraftDefinition, seconds: f32) {
+    let mut app = headless_app(definition);
+    app.finish();
+    app.cleanup();
+    spawn_ground(&mut app);
+    let aircraft = spawn_headless_aircraft(&mut app, Transform::from_xyz(0., 2., 0.));
+    app.world_mut().resource_mut::<InputAxis>().throttle = 1.;
+
+    let updates_per_second = (1. / TIMESTEP).round() as usize;
+    let updates = (seconds as f64 / TIMESTEP).ceil() as usize;
+    for update in 1..=updates {
+        app.update();
+        if update % updates_per_second != 0 && update != updates {
+            continue;
+        }
+
+        let world = app.world_mut();
+        let mut query = world.query::<(
+            &Transform,
+            &LinearVelocity,
+            &Engine,
+            &FuelTanks,
+            Option<&Crashed>,
+        )>();
+        let (transform, velocity, engine, tanks, crashed) = query.get(world, aircraft).unwrap();
+        println!(
+            "{:6.1} s  altitude {:7.1} m  speed {:5.1} m/s  {:4.0} rpm  fuel {:5.1} kg{}",
+            update as f64 * TIMESTEP,
+            transform.translation.y,
+            velocity.length(),
+            engine.rpm,
+            tanks.total(),
+            crashed.map_or(String::new(), |crashed| format!("  {}", crashed.reason)),
+        );
+    }
+}
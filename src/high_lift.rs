@@ -0,0 +1,129 @@
+/*
+Flaps, slats, speed brakes and spoilers.
+
+Flaps move between the detents from the aircraft definition at a fixed rate, slats extend together
+with the first flap detent, speed brakes and spoilers are toggled. Every device adds to the lift,
+drag and pitching moment coefficients in proportion to how far it is extended, and the resulting
+forces scale with dynamic pressure.
+*/
+
+use crate::{
+    Aircraft,
+    aircraft_definition::{AircraftDefinition, DeviceDefinition},
+    aircraft_mechanics::FlightData,
+    damage::Crashed,
+    input::ControlActions,
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+#[derive(Component, Debug, Default)]
+pub struct HighLiftDevices {
+    pub flap_detent: usize,
+    // Current flap angle in degrees.
+    pub flap_angle: f32,
+    // 0 is retracted, 1 fully extended.
+    pub slats: f32,
+    pub speed_brake: f32,
+    pub spoilers: f32,
+    pub speed_brake_selected: bool,
+    pub spoilers_selected: bool,
+}
+
+impl HighLiftDevices {
+    // (lift, drag, pitching moment) coefficients added by the devices.
+    fn coefficients(&self, definition: &AircraftDefinition) -> Vec3 {
+        let flaps = &definition.flaps;
+        let device = |device: &DeviceDefinition, extension: f32| {
+            Vec3::new(device.lift, device.drag, device.pitch) * extension
+        };
+
+        Vec3::new(
+            flaps.lift_per_degree,
+            flaps.drag_per_degree,
+            flaps.pitch_per_degree,
+        ) * self.flap_angle
+            + device(&definition.slats, self.slats)
+            + device(&definition.speed_brake, self.speed_brake)
+            + device(&definition.spoilers, self.spoilers)
+    }
+}
+
+fn move_towards(current: f32, target: f32, max_change: f32) -> f32 {
+    current + (target - current).clamp(-max_change, max_change)
+}
+
+pub fn operate_high_lift_devices(
+    time: Res<Time>,
+    actions: Res<ControlActions>,
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<&mut HighLiftDevices, With<Aircraft>>,
+) {
+    let dt = time.delta_secs();
+    let detents = definition.flaps.detents.len().max(1);
+
+    for mut devices in &mut aircraft {
+        devices.flap_detent =
+            (devices.flap_detent as i32 + actions.flaps).clamp(0, detents as i32 - 1) as usize;
+        if actions.toggle_speed_brake {
+            devices.speed_brake_selected = !devices.speed_brake_selected;
+        }
+        if actions.toggle_spoilers {
+            devices.spoilers_selected = !devices.spoilers_selected;
+        }
+
+        let target_angle = definition
+            .flaps
+            .detents
+            .get(devices.flap_detent)
+            .copied()
+            .unwrap_or(0.);
+        devices.flap_angle =
+            move_towards(devices.flap_angle, target_angle, definition.flaps.rate * dt);
+
+        let slats_target = if devices.flap_detent > 0 { 1. } else { 0. };
+        devices.slats = move_towards(
+            devices.slats,
+            slats_target,
+            dt / definition.slats.extension_time,
+        );
+
+        let speed_brake_target = if devices.speed_brake_selected { 1. } else { 0. };
+        devices.speed_brake = move_towards(
+            devices.speed_brake,
+            speed_brake_target,
+            dt / definition.speed_brake.extension_time,
+        );
+
+        let spoilers_target = if devices.spoilers_selected { 1. } else { 0. };
+        devices.spoilers = move_towards(
+            devices.spoilers,
+            spoilers_target,
+            dt / definition.spoilers.extension_time,
+        );
+    }
+}
+
+pub fn high_lift_forces(
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<
+        (Forces, &GlobalTransform, &FlightData, &HighLiftDevices),
+        (With<Aircraft>, Without<Crashed>),
+    >,
+) {
+    for (mut forces, transform, data, devices) in &mut aircraft {
+        if data.airspeed < 1. {
+            continue;
+        }
+
+        let coefficients = devices.coefficients(&definition);
+        let qs = data.dynamic_pressure * definition.wing_area;
+        let airflow = data.air_velocity.normalize();
+        let right = *transform.right();
+        // Lift is perpendicular to the airflow, in the aircraft's plane of symmetry.
+        let lift_direction = right.cross(airflow).normalize_or_zero();
+
+        forces.apply_force(lift_direction * qs * coefficients.x - airflow * qs * coefficients.y);
+        forces.apply_torque(right * qs * definition.mean_chord * coefficients.z);
+    }
+}
@@ -1,9 +1,27 @@
-use crate::{CameraSettings, InputAxis, Settings};
+use crate::{
+    FlightSimSystems, InputAxis, Settings, camera::CameraSettings, simulation::simulation_running,
+};
 use bevy::{
     input::{gamepad::GamepadEvent, keyboard::KeyboardInput},
     prelude::*,
 };
 
+// Keyboard and gamepad input, turned into `InputAxis` and `ControlActions` for the flight model.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GamepadSettings::default())
+            .insert_resource(Keymap::default())
+            .add_systems(
+                Update,
+                input_system
+                    .run_if(simulation_running)
+                    .in_set(FlightSimSystems::Input),
+            );
+    }
+}
+
 #[derive(Resource)]
 pub struct GamepadSettings {
     control_snapping_enabled: bool,
@@ -19,6 +37,18 @@ impl Default for GamepadSettings {
     }
 }
 
+// Discrete actions from the last frame, for everything that isn't an axis.
+#[derive(Resource, Default)]
+pub struct ControlActions {
+    // Flap detents to move, positive is extending.
+    pub flaps: i32,
+    pub toggle_speed_brake: bool,
+    pub toggle_spoilers: bool,
+    pub toggle_gear: bool,
+    pub toggle_canopy: bool,
+    pub toggle_door: bool,
+}
+
 #[derive(Resource)]
 pub struct Keymap {
     pub reset_camera: KeyCode,
@@ -30,7 +60,23 @@ pub struct Keymap {
     roll_right: KeyCode,
     throttle_up: KeyCode,
     throttle_down: KeyCode,
-    change_camera: KeyCode,
+    pub change_camera: KeyCode,
+    pub respawn: KeyCode,
+    pub preflight: KeyCode,
+    flaps_extend: KeyCode,
+    flaps_retract: KeyCode,
+    speed_brake: KeyCode,
+    spoilers: KeyCode,
+    gear: KeyCode,
+    canopy: KeyCode,
+    door: KeyCode,
+    pub record: KeyCode,
+    pub replay: KeyCode,
+    pub pause: KeyCode,
+    pub time_forward: KeyCode,
+    pub time_backward: KeyCode,
+    pub weather: KeyCode,
+    pub graphics: KeyCode,
 }
 
 impl Default for Keymap {
@@ -46,6 +92,22 @@ impl Default for Keymap {
             throttle_up: KeyCode::PageUp,
             throttle_down: KeyCode::PageDown,
             change_camera: KeyCode::KeyC,
+            respawn: KeyCode::Backspace,
+            preflight: KeyCode::KeyL,
+            flaps_extend: KeyCode::KeyF,
+            flaps_retract: KeyCode::KeyV,
+            speed_brake: KeyCode::KeyB,
+            spoilers: KeyCode::KeyN,
+            gear: KeyCode::KeyG,
+            canopy: KeyCode::KeyK,
+            door: KeyCode::KeyO,
+            record: KeyCode::F5,
+            replay: KeyCode::F6,
+            pause: KeyCode::Escape,
+            time_forward: KeyCode::BracketRight,
+            time_backward: KeyCode::BracketLeft,
+            weather: KeyCode::F7,
+            graphics: KeyCode::F8,
         }
     }
 }
@@ -59,6 +121,8 @@ pub fn input_system(
     settings: Res<Settings>,
     keyboard_input: Res<'_, ButtonInput<KeyCode>>,
     mut camera_settings: ResMut<CameraSettings>,
+    gamepads: Query<&Gamepad>,
+    mut actions: ResMut<ControlActions>,
 ) {
     let mut gamepad_input = InputAxis {
         pitch: 0.,
@@ -75,7 +139,32 @@ pub fn input_system(
         }
     }
 
+    *actions = ControlActions::default();
+    if keyboard_input.just_pressed(keymap.flaps_extend) {
+        actions.flaps += 1;
+    }
+    if keyboard_input.just_pressed(keymap.flaps_retract) {
+        actions.flaps -= 1;
+    }
+    actions.toggle_speed_brake = keyboard_input.just_pressed(keymap.speed_brake);
+    actions.toggle_spoilers = keyboard_input.just_pressed(keymap.spoilers);
+    actions.toggle_gear = keyboard_input.just_pressed(keymap.gear);
+    actions.toggle_canopy = keyboard_input.just_pressed(keymap.canopy);
+    actions.toggle_door = keyboard_input.just_pressed(keymap.door);
+
     if settings.gamepad_enabled {
+        for gamepad in &gamepads {
+            if gamepad.just_pressed(GamepadButton::RightTrigger) {
+                actions.flaps += 1;
+            }
+            if gamepad.just_pressed(GamepadButton::LeftTrigger) {
+                actions.flaps -= 1;
+            }
+            actions.toggle_speed_brake |= gamepad.just_pressed(GamepadButton::West);
+            actions.toggle_spoilers |= gamepad.just_pressed(GamepadButton::North);
+            actions.toggle_gear |= gamepad.just_pressed(GamepadButton::South);
+        }
+
         for event in gamepad_events.read() {
             match event {
                 GamepadEvent::Connection(e) => info!("Gamepad connection: {:?}", e),
@@ -141,10 +230,10 @@ pub fn input_system(
 }
 
 fn clamp_input_value(value: f32, gamepad_settings: &Res<GamepadSettings>) -> f32 {
-    if gamepad_settings.control_snapping_enabled {
-        if gamepad_settings.control_snapping_range.contains(&value) {
-            return 0.;
-        }
+    if gamepad_settings.control_snapping_enabled
+        && gamepad_settings.control_snapping_range.contains(&value)
+    {
+        return 0.;
     }
     value
 }
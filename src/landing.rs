@@ -0,0 +1,398 @@
+/*
+Landing analysis.
+
+The first time the wheels touch the ground after at least `MIN_FLIGHT_TIME` in the air, the
+touchdown is measured: sink rate, G load, bank, crab angle and, if it was on a runway, the distance
+from the centerline and from the threshold. The landing gets a score out of 100 and a grade, which
+are shown on the HUD, logged and appended to recordings/landings.csv.
+
+Runways come from the scenery. In Blender, add an object (an empty works) in the middle of the
+runway with the custom properties `runway` (its name, e.g. "09/27"), `runway_length` and
+`runway_width` in meters, and turn it so its local Z axis runs along the runway. Either end can be
+landed on, the threshold is the one the aircraft came from. The touchdown zone is the first third of
+the runway, but at most `TOUCHDOWN_ZONE` meters.
+*/
+
+use crate::{
+    Aircraft, FlightSimSystems, GameState,
+    aircraft_mechanics::FlightData,
+    damage::{PreviousVelocity, track_previous_velocity},
+    gear::{LandingGear, detect_weight_on_wheels},
+    loading::FlightStarting,
+    recorder::RECORDINGS_DIRECTORY,
+    simulation::simulation_running,
+};
+use bevy::prelude::*;
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+// Seconds in the air before a touchdown counts as a landing, so bounces and taxiing over bumps don't.
+const MIN_FLIGHT_TIME: f32 = 5.;
+const TOUCHDOWN_ZONE: f32 = 900.;
+// Seconds the result stays on the HUD.
+const DISPLAY_TIME: f32 = 15.;
+
+#[derive(Component, Debug, Clone)]
+pub struct Runway {
+    pub name: String,
+    pub length: f32,
+    pub width: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunwayTouchdown {
+    pub runway: String,
+    // Meters, positive right of the centerline.
+    pub centerline_offset: f32,
+    pub from_threshold: f32,
+    pub in_touchdown_zone: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LandingReport {
+    // m/s
+    pub sink_rate: f32,
+    pub g_load: f32,
+    // Degrees, positive right wing down / nose right of the track.
+    pub bank: f32,
+    pub crab: f32,
+    pub runway: Option<RunwayTouchdown>,
+    pub score: f32,
+}
+
+impl LandingReport {
+    fn new(
+        sink_rate: f32,
+        g_load: f32,
+        bank: f32,
+        crab: f32,
+        runway: Option<RunwayTouchdown>,
+    ) -> Self {
+        let mut score = 100.;
+        score -= (sink_rate - 1.).max(0.) * 20.;
+        score -= (g_load - 1.5).max(0.) * 30.;
+        score -= (bank.abs() - 3.).max(0.) * 3.;
+        score -= (crab.abs() - 3.).max(0.) * 3.;
+        match &runway {
+            Some(touchdown) => {
+                score -= (touchdown.centerline_offset.abs() - 2.).max(0.) * 2.;
+                if !touchdown.in_touchdown_zone {
+                    score -= 15.;
+                }
+            }
+            None => score -= 40.,
+        }
+
+        Self {
+            sink_rate,
+            g_load,
+            bank,
+            crab,
+            runway,
+            score: score.clamp(0., 100.),
+        }
+    }
+
+    pub fn grade(&self) -> char {
+        match self.score {
+            s if s >= 90. => 'A',
+            s if s >= 80. => 'B',
+            s if s >= 70. => 'C',
+            s if s >= 60. => 'D',
+            _ => 'F',
+        }
+    }
+
+    fn summary(&self) -> String {
+        let runway = match &self.runway {
+            Some(touchdown) => format!(
+                "runway {}, {:.1} m {} of the centerline, {:.0} m past the threshold{}",
+                touchdown.runway,
+                touchdown.centerline_offset.abs(),
+                if touchdown.centerline_offset >= 0. {
+                    "right"
+                } else {
+                    "left"
+                },
+                touchdown.from_threshold,
+                if touchdown.in_touchdown_zone {
+                    ""
+                } else {
+                    " (outside the touchdown zone)"
+                },
+            ),
+            None => "off the runway".to_string(),
+        };
+        format!(
+            "Landing {} ({:.0})\n{:.1} m/s, {:.2} g, bank {:.1}°, crab {:.1}°\n{}",
+            self.grade(),
+            self.score,
+            self.sink_rate,
+            self.g_load,
+            self.bank,
+            self.crab,
+            runway,
+        )
+    }
+
+    fn append_csv(&self, path: &Path) -> io::Result<()> {
+        let new_file = !path.exists();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if new_file {
+            writeln!(
+                file,
+                "grade,score,sink_rate,g_load,bank,crab,runway,centerline_offset,from_threshold,in_touchdown_zone"
+            )?;
+        }
+        let (runway, offset, from_threshold, in_zone) = match &self.runway {
+            Some(touchdown) => (
+                touchdown.runway.clone(),
+                touchdown.centerline_offset.to_string(),
+                touchdown.from_threshold.to_string(),
+                touchdown.in_touchdown_zone.to_string(),
+            ),
+            None => Default::default(),
+        };
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.grade(),
+            self.score,
+            self.sink_rate,
+            self.g_load,
+            self.bank,
+            self.crab,
+            runway,
+            offset,
+            from_threshold,
+            in_zone,
+        )
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LandingAnalyzer {
+    airborne_time: f32,
+    pub last: Option<LandingReport>,
+    // Seconds since the last landing.
+    since_last: f32,
+}
+
+#[derive(Component)]
+pub struct LandingText;
+
+pub struct LandingPlugin;
+
+impl Plugin for LandingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LandingAnalyzer>()
+            .add_systems(
+                OnEnter(GameState::InFlight),
+                setup_landing_ui.run_if(resource_exists::<FlightStarting>),
+            )
+            .add_systems(
+                Update,
+                (
+                    analyze_touchdown
+                        .after(detect_weight_on_wheels)
+                        .before(track_previous_velocity)
+                        .in_set(FlightSimSystems::FlightModel)
+                        .run_if(simulation_running),
+                    update_landing_ui.in_set(FlightSimSystems::Ui),
+                ),
+            );
+    }
+}
+
+fn runway_touchdown<'a>(
+    position: Vec3,
+    velocity: Vec3,
+    runways: impl IntoIterator<Item = (&'a Runway, &'a GlobalTransform)>,
+) -> Option<RunwayTouchdown> {
+    runways.into_iter().find_map(|(runway, transform)| {
+        let inverse = transform.affine().inverse();
+        let local = inverse.transform_point3(position);
+        if local.x.abs() > runway.width / 2. || local.z.abs() > runway.length / 2. {
+            return None;
+        }
+        // Coming from the +Z end, moving towards -Z, or the other way around.
+        let towards_negative_z = inverse.transform_vector3(velocity).z < 0.;
+        let (from_threshold, centerline_offset) = if towards_negative_z {
+            (runway.length / 2. - local.z, local.x)
+        } else {
+            (local.z + runway.length / 2., -local.x)
+        };
+        Some(RunwayTouchdown {
+            runway: runway.name.clone(),
+            centerline_offset,
+            from_threshold,
+            in_touchdown_zone: from_threshold <= (runway.length / 3.).min(TOUCHDOWN_ZONE),
+        })
+    })
+}
+
+// Degrees between the track and the nose, both seen from above (x east, y south). Positive when the
+// nose points right of where the aircraft is going, which is counter-clockwise from the track here.
+fn crab_angle(forward: Vec2, track: Vec2) -> f32 {
+    if track.length() > 1. && forward.length() > 0.1 {
+        track.angle_to(forward).to_degrees()
+    } else {
+        0.
+    }
+}
+
+fn analyze_touchdown(
+    time: Res<Time>,
+    mut analyzer: ResMut<LandingAnalyzer>,
+    runways: Query<(&Runway, &GlobalTransform)>,
+    aircraft: Single<(&Transform, &LandingGear, &FlightData, &PreviousVelocity), With<Aircraft>>,
+) {
+    let (transform, gear, data, previous_velocity) = *aircraft;
+    analyzer.since_last += time.delta_secs();
+    if !gear.weight_on_wheels {
+        analyzer.airborne_time += time.delta_secs();
+        return;
+    }
+    if analyzer.airborne_time < MIN_FLIGHT_TIME {
+        analyzer.airborne_time = 0.;
+        return;
+    }
+    analyzer.airborne_time = 0.;
+
+    // The velocity from before the wheels touched, the contact has already slowed it down.
+    let velocity = previous_velocity.0;
+    let right = transform.right();
+    let up = transform.up();
+    let bank = (-right.y).atan2(up.y).to_degrees();
+    let crab = crab_angle(transform.forward().xz(), velocity.xz());
+
+    let report = LandingReport::new(
+        -velocity.y,
+        data.g_load,
+        bank,
+        crab,
+        runway_touchdown(transform.translation, velocity, runways),
+    );
+    let summary = report.summary();
+    info!("{summary}");
+    let _ = fs::create_dir_all(RECORDINGS_DIRECTORY);
+    if let Err(error) = report.append_csv(&Path::new(RECORDINGS_DIRECTORY).join("landings.csv")) {
+        error!("Couldn't log the landing: {error}");
+    }
+    analyzer.last = Some(report);
+    analyzer.since_last = 0.;
+}
+
+fn setup_landing_ui(mut commands: Commands) {
+    commands.insert_resource(LandingAnalyzer::default());
+    // Centered, a bit above the middle of the screen.
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: percent(25.0),
+                width: percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            DespawnOnEnter(GameState::MainMenu),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(""), LandingText));
+        });
+}
+
+fn update_landing_ui(
+    analyzer: Res<LandingAnalyzer>,
+    mut text: Single<&mut Text, With<LandingText>>,
+) {
+    text.0 = match &analyzer.last {
+        Some(report) if analyzer.since_last < DISPLAY_TIME => report.summary(),
+        _ => String::new(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runway() -> (Runway, GlobalTransform) {
+        // 2000 m long, running north-south through the origin.
+        let runway = Runway {
+            name: "18/36".to_string(),
+            length: 2000.,
+            width: 40.,
+        };
+        (runway, GlobalTransform::IDENTITY)
+    }
+
+    #[test]
+    fn crab_is_positive_with_the_nose_right_of_the_track() {
+        // Flying north (-Z) with the nose turned 5° to the east.
+        let track = Vec3::new(0., 0., -60.);
+        let forward = Quat::from_rotation_y(-5f32.to_radians()) * Vec3::NEG_Z;
+        let crab = crab_angle(forward.xz(), track.xz());
+        assert!((crab - 5.).abs() < 1e-3, "crab {crab}");
+        let forward = Quat::from_rotation_y(5f32.to_radians()) * Vec3::NEG_Z;
+        assert!((crab_angle(forward.xz(), track.xz()) + 5.).abs() < 1e-3);
+        // Too slow to have a track.
+        assert_eq!(crab_angle(forward.xz(), Vec2::new(0., -0.5)), 0.);
+    }
+
+    #[test]
+    fn centerline_offset_is_positive_right_of_the_centerline() {
+        let (runway, transform) = runway();
+        let runways = [(&runway, &transform)];
+
+        // Landing north, 3 m east of the centerline is to the right.
+        let northbound =
+            runway_touchdown(Vec3::new(3., 0., 700.), Vec3::new(0., -1., -60.), runways).unwrap();
+        assert_eq!(northbound.centerline_offset, 3.);
+        assert_eq!(northbound.from_threshold, 300.);
+        assert!(northbound.in_touchdown_zone);
+
+        // Landing south, the same spot is to the left and the threshold is the other end.
+        let southbound =
+            runway_touchdown(Vec3::new(3., 0., 700.), Vec3::new(0., -1., 60.), runways).unwrap();
+        assert_eq!(southbound.centerline_offset, -3.);
+        assert_eq!(southbound.from_threshold, 1700.);
+        assert!(!southbound.in_touchdown_zone);
+
+        // Next to the runway.
+        assert!(runway_touchdown(Vec3::new(25., 0., 0.), Vec3::NEG_Z, runways).is_none());
+    }
+
+    #[test]
+    fn grading() {
+        let (runway, transform) = runway();
+        let on_centerline = runway_touchdown(
+            Vec3::new(0., 0., 700.),
+            Vec3::NEG_Z,
+            [(&runway, &transform)],
+        );
+
+        let greaser = LandingReport::new(0.8, 1.2, 1., 2., on_centerline.clone());
+        assert_eq!(greaser.score, 100.);
+        assert_eq!(greaser.grade(), 'A');
+
+        // 2 m/s sink costs 20 points, 5° of crab another 6.
+        let firm = LandingReport::new(2., 1.2, 0., -5., on_centerline.clone());
+        assert!((firm.score - 74.).abs() < 1e-3, "score {}", firm.score);
+        assert_eq!(firm.grade(), 'C');
+
+        // The same greaser off the runway.
+        let off_runway = LandingReport::new(0.8, 1.2, 1., 2., None);
+        assert_eq!(off_runway.score, 60.);
+        assert_eq!(off_runway.grade(), 'D');
+
+        let crash = LandingReport::new(8., 4., 20., 15., on_centerline);
+        assert_eq!(crash.score, 0.);
+        assert_eq!(crash.grade(), 'F');
+    }
+}
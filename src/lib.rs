@@ -0,0 +1,362 @@
+/*
+I made a little flight simulator here. Check out the README for further information.
+If you have fixes or want to contribute, just make a pull request (unless it's AI-generated)
+
+The simulator is a library, so it can be embedded into other Bevy apps: add `DefaultPlugins`,
+avian's `PhysicsPlugins` and `FlightSimPlugin` (see main.rs). Every part is a plugin of its own,
+and within a frame the parts run in the order of `FlightSimSystems`. The physics steps in between,
+in avian's fixed schedule.
+
+The app moves through `GameState`, from the menus (see menu.rs) to the flight. Input and the flight
+model only run while flying.
+*/
+
+pub mod aircraft_definition;
+pub mod aircraft_mechanics;
+pub mod animation;
+pub mod camera;
+pub mod damage;
+pub mod doors;
+pub mod floating_origin;
+pub mod fuel;
+pub mod gear;
+pub mod geodesy;
+pub mod graphics;
+pub mod handle_custom_properties;
+pub mod headless;
+pub mod high_lift;
+pub mod input;
+pub mod landing;
+pub mod loading;
+pub mod menu;
+pub mod mission;
+pub mod preflight;
+pub mod recorder;
+pub mod replay;
+pub mod scenery;
+pub mod simulation;
+pub mod sound;
+pub mod ssr;
+pub mod telemetry;
+pub mod terrain;
+pub mod time_of_day;
+pub mod ui;
+pub mod water;
+pub mod weather;
+pub mod weight_and_balance;
+
+use crate::{
+    aircraft_definition::AircraftDefinition,
+    animation::{AircraftModel, drive_animations, setup_animation_controller},
+    camera::CameraPlugin,
+    damage::{respawn, track_previous_velocity},
+    floating_origin::{FloatingOrigin, rebase_origin},
+    geodesy::{
+        AircraftLocation, GeoReference, LastSpawnPoint, NamedSpawnPoint, SpawnPoint,
+        update_aircraft_location,
+    },
+    graphics::{GraphicsPlugin, GraphicsSettings},
+    handle_custom_properties::ScenePropertiesPlugin,
+    input::InputPlugin,
+    landing::LandingPlugin,
+    loading::{
+        FlightStarting, RequiredAssets, flight_started, loading_error_input, setup_loading_error,
+        start_loading, wait_for_loading,
+    },
+    menu::{
+        aircraft_select_input, despawn_menu_camera, enter_pause, exit_pause, finish_boot,
+        location_select_input, main_menu_input, pause_menu_input, setup_aircraft_select,
+        setup_location_select, setup_main_menu, spawn_menu_camera, toggle_pause,
+    },
+    mission::MissionPlugin,
+    preflight::{LoadingPlan, Preflight, preflight_input, setup_preflight_ui, update_preflight_ui},
+    recorder::{FlightRecorder, record_flight, toggle_recording},
+    replay::{
+        play_replay, replay_cameras, replay_controls, setup_replay_ui, toggle_replay,
+        update_replay_ui,
+    },
+    scenery::{LoadedTiles, SceneryCatalog, stream_scenery, update_tile_colliders},
+    simulation::{FlightModelPlugin, spawn_simulated_aircraft},
+    sound::SoundPlugin,
+    telemetry::{TelemetryPlugin, TelemetrySettings},
+    terrain::{
+        Heightmap, TerrainMaterial, TerrainSettings, build_shore_map, finish_loading_heightmap,
+        load_heightmap, spawn_terrain, update_terrain_lod,
+    },
+    time_of_day::{Sun, TimeOfDay, TimeOfDayPlugin},
+    ui::HudPlugin,
+    water::WaterPlugin,
+    weather::{WeatherPlugin, WeatherPreset},
+};
+
+use avian3d::prelude::*;
+
+use bevy::{light::light_consts::lux, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub gamepad_enabled: bool,
+    #[serde(default)]
+    pub geo_reference: GeoReference,
+    #[serde(default)]
+    pub spawn: SpawnPoint,
+    // Spawn points for the location menu.
+    #[serde(default)]
+    pub locations: Vec<NamedSpawnPoint>,
+    #[serde(default = "default_aircraft")]
+    pub aircraft: String,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    // Date and time every flight starts at.
+    #[serde(default)]
+    pub time_of_day: TimeOfDay,
+    #[serde(default)]
+    pub weather: WeatherPreset,
+    #[serde(default)]
+    pub graphics: GraphicsSettings,
+}
+
+fn default_aircraft() -> String {
+    "ferris".to_string()
+}
+
+impl Settings {
+    pub fn fetch() -> Self {
+        let json_data = fs::read_to_string("settings.json").unwrap();
+        let settings: Self = serde_json::from_str(&json_data).unwrap();
+        settings
+    }
+}
+
+#[derive(Component)]
+pub struct FollowCamera;
+
+#[derive(Component)]
+pub struct Aircraft;
+
+#[derive(Resource)]
+pub struct InputAxis {
+    pub pitch: f32,    // Pitch
+    pub yaw: f32,      // Yaw
+    pub roll: f32,     // Roll
+    pub throttle: f32, // Throttle
+}
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    Boot,
+    MainMenu,
+    AircraftSelect,
+    LocationSelect,
+    // Waiting for the aircraft and scenery before the flight starts.
+    Loading,
+    LoadingFailed,
+    MissionSelect,
+    MissionResults,
+    InFlight,
+    Paused,
+    Replay,
+}
+
+// The order everything runs in within a frame.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlightSimSystems {
+    Input,
+    FlightModel,
+    Camera,
+    Ui,
+}
+
+// The whole simulator, with the settings usually read from settings.json.
+pub struct FlightSimPlugin {
+    pub settings: Settings,
+}
+
+impl Plugin for FlightSimPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = &self.settings;
+
+        app.add_plugins((
+            InputPlugin,
+            FlightModelPlugin,
+            CameraPlugin,
+            HudPlugin,
+            WaterPlugin,
+            ScenePropertiesPlugin,
+            MissionPlugin,
+            LandingPlugin,
+            TimeOfDayPlugin,
+            WeatherPlugin,
+            GraphicsPlugin {
+                settings: settings.graphics.clone(),
+            },
+            SoundPlugin,
+        ))
+        .add_plugins(MaterialPlugin::<TerrainMaterial>::default())
+        .init_state::<GameState>()
+        .configure_sets(
+            Update,
+            (
+                FlightSimSystems::Input,
+                FlightSimSystems::FlightModel,
+                FlightSimSystems::Camera,
+                FlightSimSystems::Ui,
+            )
+                .chain(),
+        )
+        .configure_sets(
+            Update,
+            (FlightSimSystems::Input, FlightSimSystems::FlightModel)
+                .run_if(in_state(GameState::InFlight)),
+        )
+        .insert_resource(settings.clone())
+        .insert_resource(settings.geo_reference.clone())
+        .insert_resource(AircraftDefinition::fetch(&settings.aircraft))
+        .insert_resource(Preflight::default())
+        .init_resource::<RequiredAssets>()
+        .insert_resource(FlightRecorder::default())
+        .insert_resource(LastSpawnPoint(settings.spawn.clone()))
+        .insert_resource(AircraftLocation::default())
+        .insert_resource(FloatingOrigin::default())
+        .insert_resource(SceneryCatalog::fetch())
+        .insert_resource(LoadedTiles::default())
+        .insert_resource(TerrainSettings::fetch())
+        .add_systems(Startup, (setup, load_heightmap))
+        .add_systems(Update, finish_boot.run_if(in_state(GameState::Boot)))
+        .add_systems(
+            OnEnter(GameState::MainMenu),
+            (spawn_menu_camera, setup_main_menu),
+        )
+        .add_systems(OnEnter(GameState::AircraftSelect), setup_aircraft_select)
+        .add_systems(OnEnter(GameState::LocationSelect), setup_location_select)
+        .add_systems(OnEnter(GameState::Loading), start_loading)
+        .add_systems(OnEnter(GameState::LoadingFailed), setup_loading_error)
+        .add_systems(
+            OnEnter(GameState::InFlight),
+            (spawn_aircraft, setup_preflight_ui, setup_replay_ui)
+                .run_if(resource_exists::<FlightStarting>),
+        )
+        .add_systems(OnEnter(GameState::InFlight), despawn_menu_camera)
+        .add_systems(OnEnter(GameState::Paused), enter_pause)
+        .add_systems(OnExit(GameState::Paused), exit_pause)
+        .add_systems(
+            Update,
+            (
+                main_menu_input.run_if(in_state(GameState::MainMenu)),
+                aircraft_select_input.run_if(in_state(GameState::AircraftSelect)),
+                location_select_input.run_if(in_state(GameState::LocationSelect)),
+                // A heightmap png that turns out to be broken is only reported once it's loaded.
+                wait_for_loading
+                    .after(stream_scenery)
+                    .after(finish_loading_heightmap)
+                    .run_if(in_state(GameState::Loading)),
+                loading_error_input.run_if(in_state(GameState::LoadingFailed)),
+                flight_started
+                    .run_if(in_state(GameState::InFlight).and(resource_exists::<FlightStarting>)),
+                toggle_pause.run_if(in_state(GameState::InFlight).or(in_state(GameState::Paused))),
+                pause_menu_input.run_if(in_state(GameState::Paused)),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                (preflight_input, update_preflight_ui)
+                    .chain()
+                    .before(FlightSimSystems::Input)
+                    .run_if(in_state(GameState::InFlight)),
+                (toggle_recording, toggle_replay)
+                    .chain()
+                    .before(FlightSimSystems::Input)
+                    .run_if(in_state(GameState::InFlight).or(in_state(GameState::Replay))),
+                (replay_controls, play_replay, replay_cameras)
+                    .chain()
+                    .after(FlightSimSystems::FlightModel)
+                    .before(FlightSimSystems::Camera)
+                    .run_if(in_state(GameState::Replay)),
+                respawn
+                    .after(track_previous_velocity)
+                    .in_set(FlightSimSystems::FlightModel),
+                (drive_animations, update_aircraft_location)
+                    .after(FlightSimSystems::FlightModel)
+                    .before(FlightSimSystems::Camera),
+                update_replay_ui.in_set(FlightSimSystems::Ui),
+                (stream_scenery, update_tile_colliders).chain(),
+                (
+                    finish_loading_heightmap,
+                    (spawn_terrain, build_shore_map).run_if(resource_added::<Heightmap>),
+                    update_terrain_lod.run_if(resource_exists::<Heightmap>),
+                )
+                    .chain(),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            record_flight.run_if(in_state(GameState::InFlight)),
+        )
+        // Runs after everything spawned during Update exists, so new entities get shifted too.
+        .add_systems(
+            PostUpdate,
+            rebase_origin.before(TransformSystems::Propagate),
+        );
+
+        if settings.telemetry.enabled {
+            app.add_plugins(TelemetryPlugin {
+                settings: settings.telemetry.clone(),
+            });
+        }
+    }
+}
+
+// Its shadows are set up by the graphics settings.
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        DirectionalLight {
+            shadows_enabled: true,
+            illuminance: lux::RAW_SUNLIGHT,
+            ..default()
+        },
+        Transform::from_xyz(2.0, 1.0, -4.0).looking_at(Vec3::ZERO, Vec3::Y),
+        Sun,
+    ));
+}
+
+// Once everything is loaded. All of it is despawned again when going back to the main menu.
+fn spawn_aircraft(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definition: Res<AircraftDefinition>,
+    spawn: Res<LastSpawnPoint>,
+    plan: Res<LoadingPlan>,
+    reference: Res<GeoReference>,
+    origin: Res<FloatingOrigin>,
+) {
+    // aircraft, the simulation plus its hitbox and model
+    let aircraft = spawn_simulated_aircraft(
+        &mut commands,
+        &definition,
+        &plan,
+        spawn.0.transform(&reference, &origin),
+    );
+    commands.entity(aircraft).insert((
+        SceneRoot(asset_server.load(GltfAssetLabel::Scene(1).from_asset(definition.model.clone()))),
+        ColliderConstructorHierarchy::new(ColliderConstructor::TrimeshFromMesh),
+        Visibility::Hidden,
+        DespawnOnEnter(GameState::MainMenu),
+    ));
+
+    commands
+        .spawn((
+            SceneRoot(
+                asset_server.load(GltfAssetLabel::Scene(0).from_asset(definition.model.clone())),
+            ),
+            Visibility::Visible,
+            ChildOf(aircraft),
+            AircraftModel {
+                gltf: asset_server.load(definition.model.clone()),
+            },
+        ))
+        .observe(setup_animation_controller);
+}
@@ -0,0 +1,150 @@
+/*
+The loading screen.
+
+Before a flight starts, everything it needs has to be loaded: the assets registered in
+`RequiredAssets` at startup (the water's normal map, the terrain's heightmap), the aircraft's model
+and the scenery tiles around the spawn point. The screen shows how many of them are done and which
+are still loading.
+
+If any of them is missing or fails to load, the error screen names it together with the error
+instead of starting the flight in an empty world. From there, Enter flies anyway and Escape goes
+back to the main menu.
+*/
+
+use crate::{
+    GameState,
+    aircraft_definition::AircraftDefinition,
+    menu::{MenuText, spawn_menu},
+    preflight::{LoadingPlan, Preflight},
+    scenery::LoadedTile,
+};
+use bevy::{asset::RecursiveDependencyLoadState, gltf::Gltf, prelude::*};
+
+// Assets every flight needs, loaded once at startup.
+#[derive(Resource, Default)]
+pub struct RequiredAssets {
+    handles: Vec<UntypedHandle>,
+    // Files that aren't loaded through the asset server and couldn't be read, with the error.
+    errors: Vec<(String, String)>,
+}
+
+impl RequiredAssets {
+    pub fn add(&mut self, handle: impl Into<UntypedHandle>) {
+        self.handles.push(handle.into());
+    }
+
+    pub fn missing(&mut self, path: &str, error: impl ToString) {
+        self.errors.push((path.to_string(), error.to_string()));
+    }
+}
+
+// What the loading screen waits for, besides the required assets and the scenery.
+#[derive(Resource)]
+pub struct LoadingAssets {
+    aircraft: Handle<Gltf>,
+}
+
+// Everything that failed to load, for the error screen.
+#[derive(Resource)]
+pub struct LoadingErrors(Vec<(String, String)>);
+
+// Set while a new flight is about to start, so entering `GameState::InFlight` spawns it only once
+// and not again when coming back from the pause menu or a replay.
+#[derive(Resource)]
+pub struct FlightStarting;
+
+pub fn start_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definition: Res<AircraftDefinition>,
+) {
+    commands.insert_resource(LoadingAssets {
+        aircraft: asset_server.load(definition.model.clone()),
+    });
+    commands.insert_resource(LoadingPlan::new(&definition));
+    commands.insert_resource(Preflight::default());
+    commands.insert_resource(FlightStarting);
+    spawn_menu(&mut commands, GameState::Loading, "Loading".to_string());
+}
+
+fn asset_name(handle: &UntypedHandle) -> String {
+    handle
+        .path()
+        .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string())
+}
+
+// Runs after the scenery is streamed in around the spawn point.
+pub fn wait_for_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    required: Res<RequiredAssets>,
+    loading: Res<LoadingAssets>,
+    tiles: Query<&SceneRoot, With<LoadedTile>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text: Single<&mut Text, With<MenuText>>,
+) {
+    let handles: Vec<UntypedHandle> = required
+        .handles
+        .iter()
+        .cloned()
+        .chain([loading.aircraft.clone().untyped()])
+        .chain(tiles.iter().map(|scene| scene.0.clone().untyped()))
+        .collect();
+
+    let mut pending = Vec::new();
+    let mut errors = required.errors.clone();
+    for handle in &handles {
+        match asset_server.get_recursive_dependency_load_state(handle.id()) {
+            Some(RecursiveDependencyLoadState::Loaded) => {}
+            Some(RecursiveDependencyLoadState::Failed(error)) => {
+                errors.push((asset_name(handle), error.to_string()));
+            }
+            _ => pending.push(asset_name(handle)),
+        }
+    }
+
+    if !errors.is_empty() {
+        for (path, error) in &errors {
+            error!("Couldn't load {path}: {error}");
+        }
+        commands.insert_resource(LoadingErrors(errors));
+        next_state.set(GameState::LoadingFailed);
+        return;
+    }
+    if pending.is_empty() {
+        next_state.set(GameState::InFlight);
+        return;
+    }
+
+    text.0 = format!(
+        "Loading\n\n{} / {} assets\n\n{}",
+        handles.len() - pending.len(),
+        handles.len(),
+        pending.join("\n"),
+    );
+}
+
+pub fn setup_loading_error(mut commands: Commands, errors: Res<LoadingErrors>) {
+    let mut lines = vec!["Couldn't load everything the flight needs\n".to_string()];
+    for (path, error) in &errors.0 {
+        lines.push(format!("{path}: {error}"));
+    }
+    lines.push("\nEnter: fly anyway, Esc: main menu".to_string());
+    spawn_menu(&mut commands, GameState::LoadingFailed, lines.join("\n"));
+}
+
+pub fn loading_error_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::InFlight);
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+pub fn flight_started(mut commands: Commands) {
+    commands.remove_resource::<FlightStarting>();
+}
@@ -0,0 +1,308 @@
+/*
+Menus.
+
+The app boots into the main menu. For a free flight, the aircraft (every definition in assets/aircraft/)
+and the location (`locations` in settings.json, or just `spawn` if there are none) are picked with
+the arrow keys and Enter, Escape goes back. Missions come with both (see mission.rs). Then the
+loading screen takes over (see loading.rs).
+
+The pause key stops the physics and opens the pause menu, where M goes back to the main menu.
+Everything belonging to a flight is despawned then.
+*/
+
+use crate::{
+    GameState, Settings,
+    aircraft_definition::AircraftDefinition,
+    geodesy::{LastSpawnPoint, NamedSpawnPoint},
+    input::Keymap,
+    preflight::Preflight,
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::fs;
+
+#[derive(Component)]
+pub struct MenuCamera;
+
+#[derive(Component)]
+pub struct MenuText;
+
+// The entry that's currently selected in a list menu.
+#[derive(Resource, Default)]
+pub struct MenuSelection(pub usize);
+
+// File name and display name of every aircraft definition.
+#[derive(Resource)]
+pub struct AvailableAircraft(Vec<(String, String)>);
+
+#[derive(Resource)]
+pub struct AvailableLocations(Vec<NamedSpawnPoint>);
+
+pub fn finish_boot(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::MainMenu);
+}
+
+// UI needs a camera, the menus get their own until the flight camera exists.
+pub fn spawn_menu_camera(mut commands: Commands, cameras: Query<(), With<MenuCamera>>) {
+    if cameras.is_empty() {
+        commands.spawn((Camera2d, MenuCamera));
+    }
+}
+
+pub fn despawn_menu_camera(mut commands: Commands, cameras: Query<Entity, With<MenuCamera>>) {
+    for camera in &cameras {
+        commands.entity(camera).despawn();
+    }
+}
+
+pub fn spawn_menu(commands: &mut Commands, state: GameState, text: String) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: percent(100.0),
+                height: percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            DespawnOnExit(state),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::all(px(20.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0., 0., 0., 0.75)),
+                ))
+                .with_children(|panel| {
+                    panel.spawn((Text::new(text), MenuText));
+                });
+        });
+}
+
+pub fn list_text<'a>(
+    title: &str,
+    entries: impl Iterator<Item = &'a str>,
+    selected: usize,
+    escape: &str,
+) -> String {
+    let mut lines = vec![format!("{title}\n")];
+    for (index, entry) in entries.enumerate() {
+        let cursor = if index == selected { ">" } else { " " };
+        lines.push(format!("{cursor} {entry}"));
+    }
+    lines.push(format!("\nUp/Down: select, Enter: continue, Esc: {escape}"));
+    lines.join("\n")
+}
+
+pub fn move_selection(
+    keyboard_input: &ButtonInput<KeyCode>,
+    selection: &mut MenuSelection,
+    len: usize,
+) {
+    let len = len.max(1);
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        selection.0 = (selection.0 + 1) % len;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        selection.0 = (selection.0 + len - 1) % len;
+    }
+}
+
+const MAIN_MENU: [&str; 3] = ["Free flight", "Missions", "Quit"];
+
+pub fn setup_main_menu(mut commands: Commands) {
+    spawn_menu(&mut commands, GameState::MainMenu, String::new());
+    commands.insert_resource(MenuSelection(0));
+}
+
+pub fn main_menu_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<MenuSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: MessageWriter<AppExit>,
+    mut text: Single<&mut Text, With<MenuText>>,
+) {
+    move_selection(&keyboard_input, &mut selection, MAIN_MENU.len());
+    text.0 = list_text("bevy-fs", MAIN_MENU.into_iter(), selection.0, "quit");
+
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        match selection.0 {
+            0 => next_state.set(GameState::AircraftSelect),
+            1 => next_state.set(GameState::MissionSelect),
+            _ => {
+                exit.write(AppExit::Success);
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit.write(AppExit::Success);
+    }
+}
+
+pub fn setup_aircraft_select(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    definition: Res<AircraftDefinition>,
+) {
+    let mut aircraft: Vec<(String, String)> = match fs::read_dir("assets/aircraft") {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                let file = path.file_stem()?.to_str()?.to_string();
+                match AircraftDefinition::load(&file) {
+                    Ok(definition) => Some((file, definition.name)),
+                    Err(error) => {
+                        warn!("Skipping aircraft {error}");
+                        None
+                    }
+                }
+            })
+            .collect(),
+        Err(error) => {
+            error!("Can't list the aircraft in assets/aircraft: {error}");
+            Vec::new()
+        }
+    };
+    aircraft.sort();
+
+    // Start at the aircraft that was flown last, or the one from the settings.
+    let selected = aircraft
+        .iter()
+        .position(|(_, name)| *name == definition.name)
+        .or_else(|| {
+            aircraft
+                .iter()
+                .position(|(file, _)| *file == settings.aircraft)
+        })
+        .unwrap_or(0);
+
+    spawn_menu(&mut commands, GameState::AircraftSelect, String::new());
+    commands.insert_resource(MenuSelection(selected));
+    commands.insert_resource(AvailableAircraft(aircraft));
+}
+
+pub fn aircraft_select_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    aircraft: Res<AvailableAircraft>,
+    mut selection: ResMut<MenuSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text: Single<&mut Text, With<MenuText>>,
+) {
+    move_selection(&keyboard_input, &mut selection, aircraft.0.len());
+    text.0 = list_text(
+        "Aircraft",
+        aircraft.0.iter().map(|(_, name)| name.as_str()),
+        selection.0,
+        "back",
+    );
+    if aircraft.0.is_empty() {
+        text.0 += "\n\nNo aircraft found in assets/aircraft, see the log for why.";
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Enter)
+        && let Some((file, _)) = aircraft.0.get(selection.0)
+    {
+        // The file may have changed since the list was made.
+        match AircraftDefinition::load(file) {
+            Ok(definition) => {
+                commands.insert_resource(definition);
+                next_state.set(GameState::LocationSelect);
+            }
+            Err(error) => warn!("Can't fly aircraft {error}"),
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+pub fn setup_location_select(mut commands: Commands, settings: Res<Settings>) {
+    let locations = if settings.locations.is_empty() {
+        vec![NamedSpawnPoint {
+            name: "Default".to_string(),
+            spawn: settings.spawn.clone(),
+        }]
+    } else {
+        settings.locations.clone()
+    };
+
+    spawn_menu(&mut commands, GameState::LocationSelect, String::new());
+    commands.insert_resource(MenuSelection(0));
+    commands.insert_resource(AvailableLocations(locations));
+}
+
+pub fn location_select_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    locations: Res<AvailableLocations>,
+    mut selection: ResMut<MenuSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text: Single<&mut Text, With<MenuText>>,
+) {
+    move_selection(&keyboard_input, &mut selection, locations.0.len());
+    text.0 = list_text(
+        "Location",
+        locations.0.iter().map(|location| location.name.as_str()),
+        selection.0,
+        "back",
+    );
+
+    if keyboard_input.just_pressed(KeyCode::Enter)
+        && let Some(location) = locations.0.get(selection.0)
+    {
+        commands.insert_resource(LastSpawnPoint(location.spawn.clone()));
+        next_state.set(GameState::Loading);
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::AircraftSelect);
+    }
+}
+
+pub fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard_input.just_pressed(keymap.pause) {
+        return;
+    }
+    match state.get() {
+        GameState::InFlight => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::InFlight),
+        _ => {}
+    }
+}
+
+pub fn enter_pause(mut commands: Commands, mut time: ResMut<Time<Physics>>) {
+    time.pause();
+    spawn_menu(
+        &mut commands,
+        GameState::Paused,
+        "Paused\n\nEsc: resume\nM: main menu".to_string(),
+    );
+}
+
+pub fn exit_pause(preflight: Res<Preflight>, mut time: ResMut<Time<Physics>>) {
+    // The preflight screen keeps the physics paused.
+    if !preflight.open {
+        time.unpause();
+    }
+}
+
+pub fn pause_menu_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyM) {
+        next_state.set(GameState::MainMenu);
+    }
+}
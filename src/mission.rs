@@ -0,0 +1,646 @@
+/*
+Missions.
+
+A mission is a json file in assets/missions/ with the aircraft to fly, where it starts, an
+optional time limit and a list of objectives that have to be done in order:
+
+- `Gate`: fly through a `width` x `height` gate centered at `location`, facing `heading`
+- `Ring`: fly through a ring of `radius` centered at `location`, facing `heading`
+- `Altitude`: get within `tolerance` of `altitude` (m)
+- `Land`: touch down on the `length` x `width` area around `location` (along `heading`) without
+  sinking faster than `max_sink_rate` (m/s), and come to a stop on it
+- `TakeOff`: lift off within `max_distance` meters of starting the takeoff roll
+
+`time_of_day` (local time in hours) sets the clock when the mission starts, `weather` is one of the
+weather presets (see weather.rs).
+
+Gates, rings and landing areas are avian sensors, only the current objective's sensor counts. Every
+objective is worth 100 points, plus a bonus for short takeoffs and soft landings, plus a point per
+second left when there's a time limit. Crashing or running out of time fails the mission. Either way,
+the results screen shows how it went.
+*/
+
+use crate::{
+    Aircraft, FlightSimSystems, GameState,
+    aircraft_definition::AircraftDefinition,
+    damage::{Crashed, Impact, apply_impacts},
+    floating_origin::FloatingOrigin,
+    gear::LandingGear,
+    geodesy::{AircraftLocation, GeoReference, Geodetic, LastSpawnPoint, SpawnPoint},
+    loading::FlightStarting,
+    menu::{MenuSelection, MenuText, list_text, move_selection, spawn_menu},
+    simulation::simulation_running,
+    weather::WeatherPreset,
+};
+use avian3d::prelude::*;
+use bevy::{color::palettes::css::ORANGE, math::DVec3, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::FRAC_PI_2, fs};
+
+// How deep gates and rings are, so fast aircraft can't skip through them within one step.
+const GATE_DEPTH: f32 = 4.;
+const LANDING_AREA_HEIGHT: f32 = 10.;
+const OBJECTIVE_POINTS: f32 = 100.;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Objective {
+    Gate {
+        location: Geodetic,
+        heading: f32,
+        width: f32,
+        height: f32,
+    },
+    Ring {
+        location: Geodetic,
+        heading: f32,
+        radius: f32,
+    },
+    Altitude {
+        altitude: f32,
+        tolerance: f32,
+    },
+    Land {
+        location: Geodetic,
+        heading: f32,
+        length: f32,
+        width: f32,
+        max_sink_rate: f32,
+    },
+    TakeOff {
+        max_distance: f32,
+    },
+}
+
+impl Objective {
+    fn describe(&self) -> String {
+        match self {
+            Objective::Gate { .. } => "Fly through the gate".to_string(),
+            Objective::Ring { .. } => "Fly through the ring".to_string(),
+            Objective::Altitude { altitude, .. } => format!("Reach {altitude:.0} m"),
+            Objective::Land { max_sink_rate, .. } => {
+                format!("Land, sinking slower than {max_sink_rate:.1} m/s")
+            }
+            Objective::TakeOff { max_distance } => {
+                format!("Take off within {max_distance:.0} m")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissionDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    // File name in assets/aircraft/, without the extension.
+    pub aircraft: String,
+    pub start: SpawnPoint,
+    #[serde(default)]
+    pub time_of_day: Option<f32>,
+    #[serde(default)]
+    pub weather: Option<WeatherPreset>,
+    // Seconds
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    pub objectives: Vec<Objective>,
+}
+
+impl MissionDefinition {
+    // The error names the file, so a half written mission only drops out of the list.
+    pub fn fetch(name: &str) -> Result<Self, String> {
+        let path = format!("assets/missions/{name}.json");
+        let json_data = fs::read_to_string(&path).map_err(|error| format!("{path}: {error}"))?;
+        serde_json::from_str(&json_data).map_err(|error| format!("{path}: {error}"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MissionOutcome {
+    Completed,
+    Failed(String),
+}
+
+// The mission being flown, removed when going back to the main menu.
+#[derive(Resource, Debug)]
+pub struct ActiveMission {
+    pub definition: MissionDefinition,
+    // Index of the current objective.
+    pub current: usize,
+    pub elapsed: f32,
+    // Points per finished objective.
+    pub points: Vec<f32>,
+    pub score: f32,
+    pub outcome: Option<MissionOutcome>,
+    // Absolute position where the takeoff roll started.
+    takeoff_start: Option<DVec3>,
+    // Aircraft colliders inside the current landing area.
+    landing_area_contacts: i32,
+    touchdown_sink_rate: Option<f32>,
+}
+
+impl ActiveMission {
+    pub fn new(definition: MissionDefinition) -> Self {
+        Self {
+            definition,
+            current: 0,
+            elapsed: 0.,
+            points: Vec::new(),
+            score: 0.,
+            outcome: None,
+            takeoff_start: None,
+            landing_area_contacts: 0,
+            touchdown_sink_rate: None,
+        }
+    }
+
+    fn complete(&mut self, points: f32) {
+        info!("Objective {} done, {points:.0} points", self.current + 1);
+        self.points.push(points);
+        self.score += points;
+        self.current += 1;
+        self.takeoff_start = None;
+        self.landing_area_contacts = 0;
+        self.touchdown_sink_rate = None;
+
+        if self.current == self.definition.objectives.len() {
+            if let Some(limit) = self.definition.time_limit {
+                self.score += (limit - self.elapsed).max(0.).floor();
+            }
+            self.outcome = Some(MissionOutcome::Completed);
+        }
+    }
+
+    fn fail(&mut self, reason: String) {
+        info!("Mission failed: {reason}");
+        self.outcome = Some(MissionOutcome::Failed(reason));
+    }
+}
+
+// The sensor of the objective with this index.
+#[derive(Component)]
+pub struct MissionTrigger(usize);
+
+#[derive(Component)]
+pub struct MissionText;
+
+// File name and mission of every mission definition.
+#[derive(Resource)]
+pub struct AvailableMissions(Vec<(String, MissionDefinition)>);
+
+pub struct MissionPlugin;
+
+impl Plugin for MissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::MissionSelect), setup_mission_select)
+            .add_systems(OnEnter(GameState::MainMenu), end_mission)
+            .add_systems(
+                OnEnter(GameState::InFlight),
+                (spawn_mission_triggers, setup_mission_ui).run_if(
+                    resource_exists::<FlightStarting>.and(resource_exists::<ActiveMission>),
+                ),
+            )
+            .add_systems(OnEnter(GameState::MissionResults), setup_mission_results)
+            .add_systems(
+                Update,
+                (
+                    mission_select_input.run_if(in_state(GameState::MissionSelect)),
+                    (mission_triggers, update_mission, finish_mission)
+                        .chain()
+                        .after(apply_impacts)
+                        .in_set(FlightSimSystems::FlightModel)
+                        .run_if(resource_exists::<ActiveMission>.and(simulation_running)),
+                    update_mission_ui
+                        .in_set(FlightSimSystems::Ui)
+                        .run_if(resource_exists::<ActiveMission>),
+                    mission_results_input.run_if(in_state(GameState::MissionResults)),
+                ),
+            );
+    }
+}
+
+fn setup_mission_select(mut commands: Commands) {
+    let mut missions: Vec<(String, MissionDefinition)> = fs::read_dir("assets/missions")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let path = entry.ok()?.path();
+                    if path.extension()? != "json" {
+                        return None;
+                    }
+                    let file = path.file_stem()?.to_str()?.to_string();
+                    match MissionDefinition::fetch(&file) {
+                        Ok(mission) => Some((file, mission)),
+                        Err(error) => {
+                            warn!("Skipping mission {error}");
+                            None
+                        }
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    missions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    spawn_menu(&mut commands, GameState::MissionSelect, String::new());
+    commands.insert_resource(MenuSelection(0));
+    commands.insert_resource(AvailableMissions(missions));
+}
+
+fn mission_select_input(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    missions: Res<AvailableMissions>,
+    mut selection: ResMut<MenuSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text: Single<&mut Text, With<MenuText>>,
+) {
+    move_selection(&keyboard_input, &mut selection, missions.0.len());
+    text.0 = list_text(
+        "Missions",
+        missions.0.iter().map(|(_, mission)| mission.name.as_str()),
+        selection.0,
+        "back",
+    );
+    if let Some((_, mission)) = missions.0.get(selection.0) {
+        text.0 += &format!("\n\n{}", mission.description);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Enter)
+        && let Some((_, mission)) = missions.0.get(selection.0)
+    {
+        match AircraftDefinition::load(&mission.aircraft) {
+            Ok(definition) => {
+                commands.insert_resource(definition);
+                commands.insert_resource(LastSpawnPoint(mission.start.clone()));
+                commands.insert_resource(ActiveMission::new(mission.clone()));
+                next_state.set(GameState::Loading);
+            }
+            Err(error) => warn!("Can't start mission {}, aircraft {error}", mission.name),
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn end_mission(mut commands: Commands) {
+    commands.remove_resource::<ActiveMission>();
+}
+
+fn spawn_mission_triggers(
+    mut commands: Commands,
+    mission: Res<ActiveMission>,
+    reference: Res<GeoReference>,
+    origin: Res<FloatingOrigin>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let material = materials.add(StandardMaterial {
+        base_color: ORANGE.into(),
+        emissive: LinearRgba::from(ORANGE) * 2.,
+        unlit: true,
+        ..default()
+    });
+    let area_material = materials.add(StandardMaterial {
+        base_color: Color::srgba(1., 0.65, 0., 0.3),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    for (index, objective) in mission.definition.objectives.iter().enumerate() {
+        let (location, heading, collider) = match objective {
+            Objective::Gate {
+                location,
+                heading,
+                width,
+                height,
+            } => (
+                *location,
+                *heading,
+                Collider::cuboid(*width, *height, GATE_DEPTH),
+            ),
+            Objective::Ring {
+                location,
+                heading,
+                radius,
+            } => (
+                *location,
+                *heading,
+                Collider::compound(vec![(
+                    Vec3::ZERO,
+                    Quat::from_rotation_x(FRAC_PI_2),
+                    Collider::cylinder(*radius, GATE_DEPTH),
+                )]),
+            ),
+            Objective::Land {
+                location,
+                heading,
+                length,
+                width,
+                ..
+            } => (
+                *location,
+                *heading,
+                // From a bit below the ground to a bit above the gear.
+                Collider::compound(vec![(
+                    Vec3::Y * (LANDING_AREA_HEIGHT / 2. - 3.),
+                    Quat::IDENTITY,
+                    Collider::cuboid(*width, LANDING_AREA_HEIGHT, *length),
+                )]),
+            ),
+            Objective::Altitude { .. } | Objective::TakeOff { .. } => continue,
+        };
+
+        let transform = SpawnPoint { location, heading }.transform(&reference, &origin);
+        let trigger = commands
+            .spawn((
+                MissionTrigger(index),
+                RigidBody::Static,
+                collider,
+                Sensor,
+                CollisionEventsEnabled,
+                transform,
+                Visibility::Visible,
+                DespawnOnEnter(GameState::MainMenu),
+            ))
+            .id();
+
+        // Something to see where the sensors are.
+        match objective {
+            Objective::Gate { width, height, .. } => {
+                let (half_width, half_height) = (width / 2., height / 2.);
+                for (size, offset) in [
+                    (vec3(1., *height, 1.), vec3(-half_width, 0., 0.)),
+                    (vec3(1., *height, 1.), vec3(half_width, 0., 0.)),
+                    (vec3(*width, 1., 1.), vec3(0., half_height, 0.)),
+                    (vec3(*width, 1., 1.), vec3(0., -half_height, 0.)),
+                ] {
+                    commands.spawn((
+                        Mesh3d(meshes.add(Cuboid::from_size(size))),
+                        MeshMaterial3d(material.clone()),
+                        Transform::from_translation(offset),
+                        ChildOf(trigger),
+                    ));
+                }
+            }
+            Objective::Ring { radius, .. } => {
+                commands.spawn((
+                    Mesh3d(meshes.add(Torus::new(radius - 0.5, radius + 0.5))),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_2)),
+                    ChildOf(trigger),
+                ));
+            }
+            Objective::Land { length, width, .. } => {
+                commands.spawn((
+                    Mesh3d(meshes.add(Plane3d::new(Vec3::Y, vec2(width / 2., length / 2.)))),
+                    MeshMaterial3d(area_material.clone()),
+                    Transform::from_xyz(0., 0.1, 0.),
+                    ChildOf(trigger),
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn mission_triggers(
+    mut commands: Commands,
+    mut started: MessageReader<CollisionStart>,
+    mut ended: MessageReader<CollisionEnd>,
+    triggers: Query<&MissionTrigger>,
+    aircraft: Query<(), With<Aircraft>>,
+    mut mission: ResMut<ActiveMission>,
+) {
+    // The current objective's trigger, if the aircraft is involved.
+    let current = mission.current;
+    let find_trigger = |colliders: [Entity; 2], bodies: [Option<Entity>; 2]| {
+        if !bodies
+            .into_iter()
+            .flatten()
+            .any(|body| aircraft.contains(body))
+        {
+            return None;
+        }
+        colliders.into_iter().find(|&collider| {
+            triggers
+                .get(collider)
+                .is_ok_and(|trigger| trigger.0 == current)
+        })
+    };
+
+    let started: Vec<_> = started
+        .read()
+        .map(|event| {
+            (
+                [event.collider1, event.collider2],
+                [event.body1, event.body2],
+            )
+        })
+        .collect();
+    let ended: Vec<_> = ended
+        .read()
+        .map(|event| {
+            (
+                [event.collider1, event.collider2],
+                [event.body1, event.body2],
+            )
+        })
+        .collect();
+
+    for (colliders, bodies) in started {
+        let Some(trigger) = find_trigger(colliders, bodies) else {
+            continue;
+        };
+        match mission.definition.objectives[current] {
+            Objective::Land { .. } => mission.landing_area_contacts += 1,
+            _ => {
+                mission.complete(OBJECTIVE_POINTS);
+                commands.entity(trigger).despawn();
+                // The rest of the events are for the finished objective.
+                return;
+            }
+        }
+    }
+
+    for (colliders, bodies) in ended {
+        if find_trigger(colliders, bodies).is_some() {
+            mission.landing_area_contacts -= 1;
+        }
+    }
+}
+
+fn update_mission(
+    time: Res<Time>,
+    origin: Res<FloatingOrigin>,
+    location: Res<AircraftLocation>,
+    mut impacts: MessageReader<Impact>,
+    mut mission: ResMut<ActiveMission>,
+    aircraft: Single<(&Transform, &LinearVelocity, &LandingGear, Option<&Crashed>), With<Aircraft>>,
+) {
+    let impacts: Vec<Impact> = impacts.read().cloned().collect();
+    if mission.outcome.is_some() {
+        return;
+    }
+    mission.elapsed += time.delta_secs();
+
+    let (transform, velocity, gear, crashed) = *aircraft;
+    if let Some(crashed) = crashed {
+        mission.fail(crashed.reason.clone());
+        return;
+    }
+    if let Some(limit) = mission.definition.time_limit
+        && mission.elapsed > limit
+    {
+        mission.fail("Ran out of time".to_string());
+        return;
+    }
+
+    let Some(objective) = mission.definition.objectives.get(mission.current).cloned() else {
+        return;
+    };
+    match objective {
+        Objective::Altitude {
+            altitude,
+            tolerance,
+        } => {
+            if (location.geodetic.altitude as f32 - altitude).abs() <= tolerance {
+                mission.complete(OBJECTIVE_POINTS);
+            }
+        }
+        Objective::TakeOff { max_distance } => {
+            let position = origin.absolute(transform.translation);
+            match mission.takeoff_start {
+                None if gear.weight_on_wheels && velocity.length() > 1. => {
+                    mission.takeoff_start = Some(position);
+                }
+                Some(start) if !gear.weight_on_wheels => {
+                    let distance = start.xz().distance(position.xz()) as f32;
+                    if distance <= max_distance {
+                        let bonus = (1. - distance / max_distance) * OBJECTIVE_POINTS;
+                        mission.complete(OBJECTIVE_POINTS + bonus);
+                    } else {
+                        mission.fail(format!(
+                            "Took {distance:.0} m to take off, {max_distance:.0} m allowed"
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Objective::Land { max_sink_rate, .. } => {
+            if mission.landing_area_contacts <= 0 {
+                return;
+            }
+            if mission.touchdown_sink_rate.is_none()
+                && let Some(impact) = impacts.first()
+            {
+                if impact.sink_rate > max_sink_rate {
+                    mission.fail(format!(
+                        "Touched down at {:.1} m/s, {max_sink_rate:.1} m/s allowed",
+                        impact.sink_rate
+                    ));
+                    return;
+                }
+                mission.touchdown_sink_rate = Some(impact.sink_rate);
+            }
+            if let Some(sink_rate) = mission.touchdown_sink_rate
+                && gear.weight_on_wheels
+                && velocity.length() < 1.
+            {
+                let bonus = (1. - sink_rate / max_sink_rate).max(0.) * OBJECTIVE_POINTS;
+                mission.complete(OBJECTIVE_POINTS + bonus);
+            }
+        }
+        Objective::Gate { .. } | Objective::Ring { .. } => {}
+    }
+}
+
+fn finish_mission(mission: Res<ActiveMission>, mut next_state: ResMut<NextState<GameState>>) {
+    if mission.outcome.is_some() {
+        next_state.set(GameState::MissionResults);
+    }
+}
+
+fn setup_mission_ui(mut commands: Commands) {
+    // Centered at the top, below the replay status.
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: px(60.0),
+                width: percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            DespawnOnEnter(GameState::MainMenu),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(""), MissionText));
+        });
+}
+
+fn update_mission_ui(mission: Res<ActiveMission>, mut text: Single<&mut Text, With<MissionText>>) {
+    let objectives = &mission.definition.objectives;
+    let objective = objectives
+        .get(mission.current)
+        .map_or("Done".to_string(), Objective::describe);
+    let time = match mission.definition.time_limit {
+        Some(limit) => format!("{:.0} / {:.0} s", mission.elapsed, limit),
+        None => format!("{:.0} s", mission.elapsed),
+    };
+    text.0 = format!(
+        "{} - {}/{}: {}\n{}  {:.0} points",
+        mission.definition.name,
+        (mission.current + 1).min(objectives.len()),
+        objectives.len(),
+        objective,
+        time,
+        mission.score,
+    );
+}
+
+fn setup_mission_results(
+    mut commands: Commands,
+    mission: Res<ActiveMission>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    time.pause();
+
+    let mut lines = vec![format!("{}\n", mission.definition.name)];
+    lines.push(match &mission.outcome {
+        Some(MissionOutcome::Failed(reason)) => format!("Failed: {reason}\n"),
+        _ => "Completed\n".to_string(),
+    });
+    for (index, objective) in mission.definition.objectives.iter().enumerate() {
+        let points = mission
+            .points
+            .get(index)
+            .map_or("-".to_string(), |points| format!("{points:.0}"));
+        lines.push(format!(
+            "{}. {}: {}",
+            index + 1,
+            objective.describe(),
+            points
+        ));
+    }
+    lines.push(format!(
+        "\nTime: {:.1} s\nScore: {:.0}",
+        mission.elapsed, mission.score
+    ));
+    lines.push("\nEnter: main menu".to_string());
+
+    let results = lines.join("\n");
+    info!("Mission results\n{results}");
+    spawn_menu(&mut commands, GameState::MissionResults, results);
+}
+
+fn mission_results_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Enter) || keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
@@ -0,0 +1,283 @@
+/*
+Pre-flight loading screen.
+
+Shown before the flight starts and whenever the preflight key is pressed. Fuel per tank and
+payload per station can be changed with the arrow keys (hold Shift for bigger steps), and the
+resulting total mass and center of mass are plotted against the aircraft's CG envelope.
+Enter applies the loading to the aircraft and starts the flight. Physics is paused meanwhile.
+During a flight it only opens on the ground and nearly stopped, so there's no refueling in the air.
+*/
+
+use crate::{
+    Aircraft, GameState,
+    aircraft_definition::AircraftDefinition,
+    fuel::FuelTanks,
+    gear::LandingGear,
+    input::Keymap,
+    weight_and_balance::{Payload, mass_properties},
+};
+use avian3d::prelude::*;
+use bevy::{
+    color::palettes::css::{LIME, RED, WHITE},
+    prelude::*,
+};
+
+const PLOT_WIDTH: f32 = 300.;
+const PLOT_HEIGHT: f32 = 200.;
+// m/s
+const MAX_GROUND_SPEED: f32 = 1.;
+
+// Fuel and payload chosen for the flight, in the same order as in the aircraft definition.
+#[derive(Resource, Debug, Clone)]
+pub struct LoadingPlan {
+    pub fuel: FuelTanks,
+    pub payload: Payload,
+}
+
+impl LoadingPlan {
+    pub fn new(definition: &AircraftDefinition) -> Self {
+        Self {
+            fuel: FuelTanks::full(definition),
+            payload: Payload::default_for(definition),
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.fuel.0.len() + self.payload.0.len()
+    }
+
+    // Adds `amount` kg to a row, keeping it within the tank's or station's limits.
+    fn adjust(&mut self, definition: &AircraftDefinition, row: usize, amount: f32) {
+        let tanks = self.fuel.0.len();
+        if row < tanks {
+            let capacity = definition.tanks[row].capacity;
+            self.fuel.0[row] = (self.fuel.0[row] + amount).clamp(0., capacity);
+        } else {
+            let max_mass = definition.stations[row - tanks].max_mass;
+            self.payload.0[row - tanks] =
+                (self.payload.0[row - tanks] + amount).clamp(0., max_mass);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Preflight {
+    pub open: bool,
+    selected: usize,
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Self {
+            open: true,
+            selected: 0,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PreflightUi;
+
+#[derive(Component)]
+pub struct PreflightText;
+
+#[derive(Component)]
+pub struct CgMarker;
+
+// Maps a (center of mass z, mass) pair to a position inside the plot.
+fn plot_position(definition: &AircraftDefinition, point: Vec2) -> Vec2 {
+    let (min, max) = definition
+        .cg_envelope
+        .iter()
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+    // Leave some room around the envelope so points outside of it can still be seen.
+    let margin = (max - min) * 0.15;
+    let normalized = (point - (min - margin)) / (max - min + margin * 2.);
+    normalized.clamp(Vec2::ZERO, Vec2::ONE) * Vec2::new(PLOT_WIDTH, PLOT_HEIGHT)
+}
+
+pub fn setup_preflight_ui(
+    mut commands: Commands,
+    definition: Res<AircraftDefinition>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    time.pause();
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: px(10.0),
+                right: px(10.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(px(10.0)),
+                row_gap: px(10.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0., 0., 0., 0.75)),
+            PreflightUi,
+            DespawnOnEnter(GameState::MainMenu),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(""), PreflightText));
+            parent
+                .spawn((
+                    Node {
+                        width: px(PLOT_WIDTH),
+                        height: px(PLOT_HEIGHT),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1., 1., 1., 0.1)),
+                ))
+                .with_children(|plot| {
+                    // Draw the envelope's outline as a row of dots.
+                    let envelope = &definition.cg_envelope;
+                    for (i, &start) in envelope.iter().enumerate() {
+                        let end = envelope[(i + 1) % envelope.len()];
+                        for step in 0..20 {
+                            let position =
+                                plot_position(&definition, start.lerp(end, step as f32 / 20.));
+                            plot.spawn((
+                                Node {
+                                    position_type: PositionType::Absolute,
+                                    left: px(position.x - 1.),
+                                    bottom: px(position.y - 1.),
+                                    width: px(2.),
+                                    height: px(2.),
+                                    ..default()
+                                },
+                                BackgroundColor(WHITE.into()),
+                            ));
+                        }
+                    }
+                    plot.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            width: px(8.),
+                            height: px(8.),
+                            ..default()
+                        },
+                        BackgroundColor(LIME.into()),
+                        CgMarker,
+                    ));
+                });
+        });
+}
+
+pub fn preflight_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    definition: Res<AircraftDefinition>,
+    mut preflight: ResMut<Preflight>,
+    mut plan: ResMut<LoadingPlan>,
+    mut time: ResMut<Time<Physics>>,
+    mut aircraft: Query<
+        (&mut FuelTanks, &mut Payload, &LandingGear, &LinearVelocity),
+        With<Aircraft>,
+    >,
+) {
+    if !preflight.open {
+        let parked = aircraft.iter().all(|(_, _, gear, velocity)| {
+            gear.weight_on_wheels && velocity.length() < MAX_GROUND_SPEED
+        });
+        if keyboard_input.just_pressed(keymap.preflight) && parked {
+            preflight.open = true;
+            time.pause();
+        }
+        return;
+    }
+
+    let rows = plan.rows().max(1);
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        preflight.selected = (preflight.selected + 1) % rows;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        preflight.selected = (preflight.selected + rows - 1) % rows;
+    }
+
+    let step = if keyboard_input.pressed(KeyCode::ShiftLeft) {
+        50.
+    } else {
+        10.
+    };
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        plan.adjust(&definition, preflight.selected, step);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        plan.adjust(&definition, preflight.selected, -step);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        for (mut tanks, mut payload, _, _) in &mut aircraft {
+            *tanks = plan.fuel.clone();
+            *payload = plan.payload.clone();
+        }
+        preflight.open = false;
+        time.unpause();
+    }
+}
+
+pub fn update_preflight_ui(
+    preflight: Res<Preflight>,
+    plan: Res<LoadingPlan>,
+    definition: Res<AircraftDefinition>,
+    mut root: Single<&mut Visibility, With<PreflightUi>>,
+    mut text: Single<&mut Text, With<PreflightText>>,
+    mut marker: Single<(&mut Node, &mut BackgroundColor), With<CgMarker>>,
+) {
+    **root = if preflight.open {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    if !preflight.open {
+        return;
+    }
+
+    let (mass, center_of_mass) = mass_properties(&definition, &plan.fuel, &plan.payload);
+    let overweight = mass > definition.max_takeoff_mass;
+    let within_envelope = definition.cg_within_envelope(center_of_mass.z, mass);
+
+    let mut lines = vec![format!("{} - loading\n", definition.name)];
+    let tanks = definition
+        .tanks
+        .iter()
+        .zip(&plan.fuel.0)
+        .map(|(tank, mass)| format!("Fuel {}: {:.0} / {:.0} kg", tank.name, mass, tank.capacity));
+    let stations = definition
+        .stations
+        .iter()
+        .zip(&plan.payload.0)
+        .map(|(station, mass)| {
+            format!("{}: {:.0} / {:.0} kg", station.name, mass, station.max_mass)
+        });
+    for (row, line) in tanks.chain(stations).enumerate() {
+        let cursor = if row == preflight.selected { ">" } else { " " };
+        lines.push(format!("{cursor} {line}"));
+    }
+
+    lines.push(format!(
+        "\nTotal: {:.0} kg (max {:.0} kg)\nCG: {:.2} m",
+        mass, definition.max_takeoff_mass, center_of_mass.z
+    ));
+    if overweight {
+        lines.push("WARNING: above maximum takeoff mass".to_string());
+    }
+    if !within_envelope {
+        lines.push("WARNING: center of gravity outside of the envelope".to_string());
+    }
+    lines.push("\nUp/Down: select, Left/Right: change (Shift: 50 kg), Enter: fly".to_string());
+    text.0 = lines.join("\n");
+
+    let position = plot_position(&definition, Vec2::new(center_of_mass.z, mass));
+    let (node, color) = &mut *marker;
+    node.left = px(position.x - 4.);
+    node.bottom = px(position.y - 4.);
+    color.0 = if overweight || !within_envelope {
+        RED.into()
+    } else {
+        LIME.into()
+    };
+}
@@ -0,0 +1,406 @@
+/*
+Flight data recorder.
+
+While recording, the aircraft's state is sampled every fixed tick and, when the recording stops,
+written to recordings/flight-<unix time>.bfr together with a .csv copy for analysis.
+
+The .bfr format is little endian: the magic bytes "BFSR", a u32 version, a u32 sample count and
+then the samples one after another, each being the time (f32), the absolute position (3 f64), the
+rotation (4 f32), the linear and angular velocity (3 f32 each), pitch, roll, yaw and throttle,
+the engine RPM, the gear position and the flap angle (f32 each).
+*/
+
+use crate::{
+    Aircraft, InputAxis, aircraft_mechanics::Engine, floating_origin::FloatingOrigin,
+    gear::LandingGear, high_lift::HighLiftDevices, input::Keymap, replay::Replay,
+};
+use avian3d::prelude::*;
+use bevy::{math::DVec3, prelude::*};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &[u8; 4] = b"BFSR";
+const VERSION: u32 = 1;
+pub const RECORDINGS_DIRECTORY: &str = "recordings";
+
+#[derive(Debug, Clone, Copy)]
+pub struct FlightSample {
+    // Seconds since the recording started.
+    pub time: f32,
+    // Absolute, so recordings survive rebasing of the floating origin.
+    pub position: DVec3,
+    pub rotation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub pitch: f32,
+    pub roll: f32,
+    pub yaw: f32,
+    pub throttle: f32,
+    pub rpm: f32,
+    pub gear: f32,
+    pub flaps: f32,
+}
+
+impl FlightSample {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            time: self.time.lerp(other.time, t),
+            position: self.position.lerp(other.position, t as f64),
+            rotation: self.rotation.slerp(other.rotation, t),
+            linear_velocity: self.linear_velocity.lerp(other.linear_velocity, t),
+            angular_velocity: self.angular_velocity.lerp(other.angular_velocity, t),
+            pitch: self.pitch.lerp(other.pitch, t),
+            roll: self.roll.lerp(other.roll, t),
+            yaw: self.yaw.lerp(other.yaw, t),
+            throttle: self.throttle.lerp(other.throttle, t),
+            rpm: self.rpm.lerp(other.rpm, t),
+            gear: self.gear.lerp(other.gear, t),
+            flaps: self.flaps.lerp(other.flaps, t),
+        }
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.time.to_le_bytes());
+        for value in self.position.to_array() {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let floats = [
+            self.rotation.to_array().as_slice(),
+            &self.linear_velocity.to_array(),
+            &self.angular_velocity.to_array(),
+            &[
+                self.pitch,
+                self.roll,
+                self.yaw,
+                self.throttle,
+                self.rpm,
+                self.gear,
+                self.flaps,
+            ],
+        ]
+        .concat();
+        for value in floats {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let time = read_f32(reader)?;
+        let position = DVec3::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+        let rotation = Quat::from_xyzw(
+            read_f32(reader)?,
+            read_f32(reader)?,
+            read_f32(reader)?,
+            read_f32(reader)?,
+        );
+        let linear_velocity = read_vec3(reader)?;
+        let angular_velocity = read_vec3(reader)?;
+
+        Ok(Self {
+            time,
+            position,
+            rotation,
+            linear_velocity,
+            angular_velocity,
+            pitch: read_f32(reader)?,
+            roll: read_f32(reader)?,
+            yaw: read_f32(reader)?,
+            throttle: read_f32(reader)?,
+            rpm: read_f32(reader)?,
+            gear: read_f32(reader)?,
+            flaps: read_f32(reader)?,
+        })
+    }
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_vec3(reader: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        read_f32(reader)?,
+        read_f32(reader)?,
+        read_f32(reader)?,
+    ))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FlightRecording {
+    pub samples: Vec<FlightSample>,
+}
+
+impl FlightRecording {
+    pub fn duration(&self) -> f32 {
+        self.samples.last().map_or(0., |sample| sample.time)
+    }
+
+    // Interpolated between the two samples around `time`.
+    pub fn sample_at(&self, time: f32) -> Option<FlightSample> {
+        let next = self.samples.partition_point(|sample| sample.time < time);
+        let after = self
+            .samples
+            .get(next.min(self.samples.len().saturating_sub(1)))?;
+        let Some(before) = next.checked_sub(1).and_then(|i| self.samples.get(i)) else {
+            return Some(*after);
+        };
+        let span = after.time - before.time;
+        let t = if span > 0. {
+            ((time - before.time) / span).clamp(0., 1.)
+        } else {
+            0.
+        };
+        Some(before.lerp(after, t))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut buffer = Vec::with_capacity(12 + self.samples.len() * 96);
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&VERSION.to_le_bytes());
+        buffer.extend_from_slice(&(self.samples.len() as u32).to_le_bytes());
+        for sample in &self.samples {
+            sample.write(&mut buffer);
+        }
+        fs::write(path, buffer)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a flight recording",
+            ));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported recording version {version}"),
+            ));
+        }
+
+        let count = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let samples = (0..count)
+            .map(|_| FlightSample::read(&mut file))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { samples })
+    }
+
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(
+            file,
+            "time,x,y,z,qx,qy,qz,qw,vx,vy,vz,wx,wy,wz,pitch,roll,yaw,throttle,rpm,gear,flaps"
+        )?;
+        for s in &self.samples {
+            let values = [
+                s.rotation.to_array().as_slice(),
+                &s.linear_velocity.to_array(),
+                &s.angular_velocity.to_array(),
+                &[s.pitch, s.roll, s.yaw, s.throttle, s.rpm, s.gear, s.flaps],
+            ]
+            .concat()
+            .iter()
+            .map(f32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                s.time, s.position.x, s.position.y, s.position.z, values
+            )?;
+        }
+        file.flush()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct FlightRecorder {
+    // Some while recording.
+    pub recording: Option<FlightRecording>,
+    elapsed: f32,
+}
+
+// The newest .bfr file in the recordings directory.
+pub fn latest_recording() -> Option<PathBuf> {
+    fs::read_dir(RECORDINGS_DIRECTORY)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bfr"))
+        .max()
+}
+
+fn save_recording(recording: &FlightRecording) -> io::Result<PathBuf> {
+    fs::create_dir_all(RECORDINGS_DIRECTORY)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = Path::new(RECORDINGS_DIRECTORY).join(format!("flight-{timestamp}.bfr"));
+    recording.save(&path)?;
+    recording.export_csv(&path.with_extension("csv"))?;
+    Ok(path)
+}
+
+pub fn toggle_recording(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    replay: Option<Res<Replay>>,
+    mut recorder: ResMut<FlightRecorder>,
+) {
+    if !keyboard_input.just_pressed(keymap.record) || replay.is_some() {
+        return;
+    }
+
+    match recorder.recording.take() {
+        Some(recording) => match save_recording(&recording) {
+            Ok(path) => info!(
+                "Saved {:.1} s of flight to {}",
+                recording.duration(),
+                path.display()
+            ),
+            Err(error) => error!("Couldn't save the recording: {error}"),
+        },
+        None => {
+            info!("Recording started");
+            recorder.recording = Some(FlightRecording::default());
+            recorder.elapsed = 0.;
+        }
+    }
+}
+
+// Runs in FixedUpdate, so samples are evenly spaced.
+pub fn record_flight(
+    time: Res<Time>,
+    input: Res<InputAxis>,
+    origin: Res<FloatingOrigin>,
+    mut recorder: ResMut<FlightRecorder>,
+    aircraft: Single<
+        (
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            &AngularVelocity,
+            &Engine,
+            &LandingGear,
+            &HighLiftDevices,
+        ),
+        With<Aircraft>,
+    >,
+) {
+    let elapsed = recorder.elapsed;
+    let Some(recording) = &mut recorder.recording else {
+        return;
+    };
+    let (position, rotation, linear, angular, engine, gear, devices) = *aircraft;
+
+    recording.samples.push(FlightSample {
+        time: elapsed,
+        position: origin.absolute(position.0),
+        rotation: rotation.0,
+        linear_velocity: linear.0,
+        angular_velocity: angular.0,
+        pitch: input.pitch,
+        roll: input.roll,
+        yaw: input.yaw,
+        throttle: input.throttle,
+        rpm: engine.rpm,
+        gear: gear.position,
+        flaps: devices.flap_angle,
+    });
+    recorder.elapsed += time.delta_secs();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: f32, x: f64, rpm: f32) -> FlightSample {
+        FlightSample {
+            time,
+            position: DVec3::new(x, 100., -x),
+            rotation: Quat::from_rotation_y(time),
+            linear_velocity: Vec3::new(0., 0., -50.),
+            angular_velocity: Vec3::new(0.1, 0., 0.),
+            pitch: 0.2,
+            roll: -0.3,
+            yaw: 0.,
+            throttle: 0.8,
+            rpm,
+            gear: 1.,
+            flaps: 10.,
+        }
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let recording = FlightRecording {
+            samples: vec![sample(0., 1.5e6, 2000.), sample(0.5, 1.5e6 + 25., 2100.)],
+        };
+        let path = std::env::temp_dir().join(format!("bevy_fs_test_{}.bfr", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = FlightRecording::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.samples.len(), 2);
+        for (saved, loaded) in recording.samples.iter().zip(&loaded.samples) {
+            assert_eq!(saved.time, loaded.time);
+            // f64, so large absolute positions keep their precision.
+            assert_eq!(saved.position, loaded.position);
+            assert_eq!(saved.rotation, loaded.rotation);
+            assert_eq!(saved.linear_velocity, loaded.linear_velocity);
+            assert_eq!(saved.angular_velocity, loaded.angular_velocity);
+            assert_eq!(
+                [saved.pitch, saved.roll, saved.yaw, saved.throttle],
+                [loaded.pitch, loaded.roll, loaded.yaw, loaded.throttle]
+            );
+            assert_eq!(
+                [saved.rpm, saved.gear, saved.flaps],
+                [loaded.rpm, loaded.gear, loaded.flaps]
+            );
+        }
+    }
+
+    #[test]
+    fn load_rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("bevy_fs_test_{}.txt", std::process::id()));
+        fs::write(&path, b"not a recording at all").unwrap();
+        let loaded = FlightRecording::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn sample_at_interpolates_between_samples() {
+        let recording = FlightRecording {
+            samples: vec![sample(0., 0., 1000.), sample(1., 10., 2000.)],
+        };
+        let middle = recording.sample_at(0.25).unwrap();
+        assert_eq!(middle.time, 0.25);
+        assert!((middle.position.x - 2.5).abs() < 1e-9);
+        assert!((middle.rpm - 1250.).abs() < 1e-3);
+        assert!(middle.rotation.angle_between(Quat::from_rotation_y(0.25)) < 1e-4);
+
+        // Clamped to the first and last sample outside of the recording.
+        assert_eq!(recording.sample_at(-1.).unwrap().rpm, 1000.);
+        assert_eq!(recording.sample_at(5.).unwrap().rpm, 2000.);
+        assert!(FlightRecording::default().sample_at(0.).is_none());
+    }
+}
@@ -0,0 +1,342 @@
+/*
+Flight replay.
+
+Plays back the newest recording in the recordings directory (see recorder.rs) with the physics
+paused. The aircraft, its controls, engine, gear and flaps follow the recording, so the animations
+play along. When the replay is stopped, the flight continues from the replayed state.
+
+Space pauses, Left/Right seek 10 seconds, Up/Down change the speed and the camera key cycles
+between the follow, free and flyby cameras. The free camera moves with WASD, Q and E (hold Shift to
+go faster) and looks around while the right mouse button is held.
+*/
+
+use crate::{
+    Aircraft, FollowCamera, GameState, InputAxis,
+    aircraft_mechanics::Engine,
+    camera::CameraSettings,
+    floating_origin::FloatingOrigin,
+    gear::LandingGear,
+    high_lift::HighLiftDevices,
+    input::Keymap,
+    preflight::Preflight,
+    recorder::{FlightRecorder, FlightRecording, RECORDINGS_DIRECTORY, latest_recording},
+};
+use avian3d::prelude::*;
+use bevy::{input::mouse::AccumulatedMouseMotion, prelude::*};
+
+const SEEK_STEP: f32 = 10.;
+const FREE_CAMERA_SPEED: f32 = 40.;
+// How far ahead of the aircraft the flyby camera waits, and how far the aircraft gets past it
+// before it moves on.
+const FLYBY_LEAD: f32 = 250.;
+const FLYBY_TRAIL: f32 = 150.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCamera {
+    Follow,
+    Free,
+    Flyby,
+}
+
+#[derive(Resource)]
+pub struct Replay {
+    recording: FlightRecording,
+    pub time: f32,
+    pub speed: f32,
+    pub paused: bool,
+    pub camera: ReplayCamera,
+}
+
+#[derive(Component)]
+pub struct ReplayText;
+
+pub fn follow_camera_active(replay: Option<Res<Replay>>) -> bool {
+    replay.is_none_or(|replay| replay.camera == ReplayCamera::Follow)
+}
+
+fn attach_follow_camera(
+    commands: &mut Commands,
+    camera: Entity,
+    transform: &mut Transform,
+    aircraft: Entity,
+    camera_settings: &CameraSettings,
+) {
+    commands.entity(camera).insert(ChildOf(aircraft));
+    *transform = Transform::from_translation(camera_settings.follow_default_position)
+        .looking_at(camera_settings.follow_default_lookat, Vec3::Y);
+}
+
+pub fn toggle_replay(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    replay: Option<Res<Replay>>,
+    recorder: Res<FlightRecorder>,
+    preflight: Res<Preflight>,
+    camera_settings: Res<CameraSettings>,
+    mut time: ResMut<Time<Physics>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut camera: Single<(Entity, &mut Transform), With<FollowCamera>>,
+    aircraft: Single<Entity, With<Aircraft>>,
+) {
+    if !keyboard_input.just_pressed(keymap.replay) {
+        return;
+    }
+
+    if let Some(replay) = replay {
+        if replay.camera != ReplayCamera::Follow {
+            let (entity, transform) = &mut *camera;
+            attach_follow_camera(
+                &mut commands,
+                *entity,
+                transform,
+                *aircraft,
+                &camera_settings,
+            );
+        }
+        commands.remove_resource::<Replay>();
+        next_state.set(GameState::InFlight);
+        if !preflight.open {
+            time.unpause();
+        }
+        return;
+    }
+
+    // Both use the arrow keys.
+    if preflight.open {
+        info!("Close the loading screen before starting a replay");
+        return;
+    }
+    if recorder.recording.is_some() {
+        info!("Stop recording before starting a replay");
+        return;
+    }
+    let Some(path) = latest_recording() else {
+        info!("No recordings in {RECORDINGS_DIRECTORY}/");
+        return;
+    };
+    match FlightRecording::load(&path) {
+        Ok(recording) if !recording.samples.is_empty() => {
+            info!("Replaying {}", path.display());
+            time.pause();
+            next_state.set(GameState::Replay);
+            commands.insert_resource(Replay {
+                recording,
+                time: 0.,
+                speed: 1.,
+                paused: false,
+                camera: ReplayCamera::Follow,
+            });
+        }
+        Ok(_) => info!("{} is empty", path.display()),
+        Err(error) => error!("Couldn't load {}: {error}", path.display()),
+    }
+}
+
+pub fn replay_controls(keyboard_input: Res<ButtonInput<KeyCode>>, mut replay: ResMut<Replay>) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        replay.paused = !replay.paused;
+    }
+    let duration = replay.recording.duration();
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        replay.time = (replay.time - SEEK_STEP).max(0.);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        replay.time = (replay.time + SEEK_STEP).min(duration);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        replay.speed = (replay.speed * 2.).min(8.);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        replay.speed = (replay.speed / 2.).max(0.125);
+    }
+}
+
+pub fn play_replay(
+    time: Res<Time>,
+    origin: Res<FloatingOrigin>,
+    mut replay: ResMut<Replay>,
+    mut input: ResMut<InputAxis>,
+    aircraft: Single<
+        (
+            &mut Transform,
+            &mut Position,
+            &mut Rotation,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+            &mut Engine,
+            &mut LandingGear,
+            &mut HighLiftDevices,
+        ),
+        With<Aircraft>,
+    >,
+) {
+    if !replay.paused {
+        replay.time =
+            (replay.time + time.delta_secs() * replay.speed).min(replay.recording.duration());
+    }
+    let Some(sample) = replay.recording.sample_at(replay.time) else {
+        return;
+    };
+
+    let (
+        mut transform,
+        mut position,
+        mut rotation,
+        mut linear,
+        mut angular,
+        mut engine,
+        mut gear,
+        mut devices,
+    ) = aircraft.into_inner();
+    transform.translation = origin.local(sample.position);
+    transform.rotation = sample.rotation;
+    position.0 = transform.translation;
+    rotation.0 = sample.rotation;
+    linear.0 = sample.linear_velocity;
+    angular.0 = sample.angular_velocity;
+
+    input.pitch = sample.pitch;
+    input.roll = sample.roll;
+    input.yaw = sample.yaw;
+    input.throttle = sample.throttle;
+    engine.rpm = sample.rpm;
+    gear.position = sample.gear;
+    gear.down_selected = sample.gear > 0.5;
+    devices.flap_angle = sample.flaps;
+}
+
+pub fn replay_cameras(
+    mut commands: Commands,
+    time: Res<Time>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    keymap: Res<Keymap>,
+    camera_settings: Res<CameraSettings>,
+    mut replay: ResMut<Replay>,
+    mut camera: Single<
+        (Entity, &mut Transform, &GlobalTransform),
+        (With<FollowCamera>, Without<Aircraft>),
+    >,
+    aircraft: Single<(Entity, &Transform, &LinearVelocity), With<Aircraft>>,
+) {
+    let (camera_entity, transform, global_transform) = &mut *camera;
+    let (aircraft_entity, aircraft_transform, velocity) = *aircraft;
+
+    let mut place_flyby = false;
+    if keyboard_input.just_pressed(keymap.change_camera) {
+        replay.camera = match replay.camera {
+            ReplayCamera::Follow => ReplayCamera::Free,
+            ReplayCamera::Free => ReplayCamera::Flyby,
+            ReplayCamera::Flyby => ReplayCamera::Follow,
+        };
+        match replay.camera {
+            ReplayCamera::Follow => attach_follow_camera(
+                &mut commands,
+                *camera_entity,
+                transform,
+                aircraft_entity,
+                &camera_settings,
+            ),
+            ReplayCamera::Free => {
+                // Detach the camera where it is.
+                commands.entity(*camera_entity).remove::<ChildOf>();
+                **transform = global_transform.compute_transform();
+            }
+            ReplayCamera::Flyby => place_flyby = true,
+        }
+    }
+
+    match replay.camera {
+        ReplayCamera::Follow => {}
+        ReplayCamera::Free => {
+            let mut direction = Vec3::ZERO;
+            for (key, axis) in [
+                (KeyCode::KeyW, transform.forward()),
+                (KeyCode::KeyS, transform.back()),
+                (KeyCode::KeyA, transform.left()),
+                (KeyCode::KeyD, transform.right()),
+                (KeyCode::KeyE, Dir3::Y),
+                (KeyCode::KeyQ, Dir3::NEG_Y),
+            ] {
+                if keyboard_input.pressed(key) {
+                    direction += *axis;
+                }
+            }
+            let boost = if keyboard_input.pressed(KeyCode::ShiftLeft) {
+                5.
+            } else {
+                1.
+            };
+            transform.translation +=
+                direction.normalize_or_zero() * FREE_CAMERA_SPEED * boost * time.delta_secs();
+
+            if mouse_buttons.pressed(MouseButton::Right) {
+                let delta = mouse_motion.delta;
+                let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+                let pitch = (pitch - delta.y * camera_settings.pitch_speed).clamp(
+                    camera_settings.pitch_range.start,
+                    camera_settings.pitch_range.end,
+                );
+                let yaw = yaw - delta.x * camera_settings.yaw_speed;
+                transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.);
+            }
+        }
+        ReplayCamera::Flyby => {
+            let target = aircraft_transform.translation;
+            let offset = target - transform.translation;
+            // Move on once the aircraft has passed far enough.
+            let passed = offset.dot(velocity.0) > 0. && offset.length() > FLYBY_TRAIL;
+            if place_flyby || passed {
+                if place_flyby {
+                    commands.entity(*camera_entity).remove::<ChildOf>();
+                }
+                let heading = velocity
+                    .0
+                    .try_normalize()
+                    .unwrap_or(*aircraft_transform.forward());
+                let side = heading.cross(Vec3::Y).normalize_or_zero();
+                transform.translation = target + heading * FLYBY_LEAD + side * 20. + Vec3::Y * 5.;
+            }
+            transform.look_at(target, Vec3::Y);
+        }
+    }
+}
+
+pub fn setup_replay_ui(mut commands: Commands) {
+    // Centered at the top of the screen.
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: px(10.0),
+                width: percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            DespawnOnEnter(GameState::MainMenu),
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(""), ReplayText));
+        });
+}
+
+pub fn update_replay_ui(
+    replay: Option<Res<Replay>>,
+    recorder: Res<FlightRecorder>,
+    mut text: Single<&mut Text, With<ReplayText>>,
+) {
+    text.0 = match replay {
+        Some(replay) => format!(
+            "REPLAY {:.1} / {:.1} s  x{}{}  {:?} camera\nSpace: pause, Left/Right: seek, Up/Down: speed",
+            replay.time,
+            replay.recording.duration(),
+            replay.speed,
+            if replay.paused { "  paused" } else { "" },
+            replay.camera,
+        ),
+        None if recorder.recording.is_some() => "REC".to_string(),
+        None => String::new(),
+    };
+}
@@ -0,0 +1,201 @@
+/*
+Scenery streaming.
+
+The world is split into tiles listed in assets/scenery/catalog.json. Every tile is a glTF scene
+exported with custom properties (see handle_custom_properties.rs for the collider format), placed
+either at an absolute world `position` or at a geodetic `location`, and covering roughly `radius`
+meters around it.
+
+A tile is spawned once the aircraft gets closer than `load_distance` to its edge and despawned
+once it's further away than `unload_distance`, so flying along the border doesn't reload it every
+frame. Colliders are only enabled for tiles closer than `collider_distance`.
+*/
+
+use crate::{
+    Aircraft,
+    floating_origin::FloatingOrigin,
+    geodesy::{GeoReference, Geodetic, LastSpawnPoint},
+    handle_custom_properties::SceneProperties,
+};
+use avian3d::prelude::*;
+use bevy::{gltf::GltfMeshExtras, math::DVec3, prelude::*, scene::SceneInstanceReady};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SceneryTile {
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub position: Option<Vec3>,
+    #[serde(default)]
+    pub location: Option<Geodetic>,
+    pub radius: f32,
+}
+
+impl SceneryTile {
+    fn absolute_position(&self, reference: &GeoReference) -> DVec3 {
+        match (self.position, self.location) {
+            (_, Some(location)) => reference.geodetic_to_world(location),
+            (Some(position), None) => position.as_dvec3(),
+            (None, None) => {
+                warn!(
+                    "Scenery tile {} has neither a position nor a location",
+                    self.name
+                );
+                DVec3::ZERO
+            }
+        }
+    }
+}
+
+#[derive(Resource, Debug, Serialize, Deserialize)]
+pub struct SceneryCatalog {
+    pub load_distance: f32,
+    pub unload_distance: f32,
+    pub collider_distance: f32,
+    pub tiles: Vec<SceneryTile>,
+}
+
+impl SceneryCatalog {
+    pub fn fetch() -> Self {
+        let json_data = fs::read_to_string("assets/scenery/catalog.json").unwrap();
+        let catalog: Self = serde_json::from_str(&json_data).unwrap();
+        catalog
+    }
+
+    // Distance from the absolute position `point` to the edge of a tile, 0 if the point is inside it.
+    fn distance_to(&self, index: usize, point: DVec3, reference: &GeoReference) -> f32 {
+        let tile = &self.tiles[index];
+        let distance = tile.absolute_position(reference).xz().distance(point.xz()) as f32;
+        (distance - tile.radius).max(0.)
+    }
+}
+
+// Tiles that are currently spawned, by catalog index.
+#[derive(Resource, Default)]
+pub struct LoadedTiles(HashMap<usize, Entity>);
+
+#[derive(Component)]
+pub struct LoadedTile {
+    colliders_enabled: bool,
+}
+
+pub fn stream_scenery(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    catalog: Res<SceneryCatalog>,
+    mut loaded: ResMut<LoadedTiles>,
+    origin: Res<FloatingOrigin>,
+    reference: Res<GeoReference>,
+    spawn: Res<LastSpawnPoint>,
+    aircraft: Option<Single<&Transform, With<Aircraft>>>,
+) {
+    // Before the aircraft is spawned, load the scenery around where it's going to be.
+    let position = match aircraft {
+        Some(aircraft) => origin.absolute(aircraft.translation),
+        None => reference.geodetic_to_world(spawn.0.location),
+    };
+
+    for (index, tile) in catalog.tiles.iter().enumerate() {
+        let distance = catalog.distance_to(index, position, &reference);
+
+        match loaded.0.get(&index) {
+            None if distance < catalog.load_distance => {
+                info!("Loading scenery tile {}", tile.name);
+                let entity = commands
+                    .spawn((
+                        SceneRoot(
+                            asset_server
+                                .load(GltfAssetLabel::Scene(0).from_asset(tile.path.clone())),
+                        ),
+                        Transform::from_translation(
+                            origin.local(tile.absolute_position(&reference)),
+                        ),
+                        LoadedTile {
+                            colliders_enabled: distance < catalog.collider_distance,
+                        },
+                        Name::new(tile.name.clone()),
+                        SceneProperties,
+                    ))
+                    .observe(apply_collider_state_when_ready)
+                    .id();
+                loaded.0.insert(index, entity);
+            }
+            Some(&entity) if distance > catalog.unload_distance => {
+                info!("Unloading scenery tile {}", tile.name);
+                commands.entity(entity).despawn();
+                loaded.0.remove(&index);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Turns tile colliders on or off depending on how far away the aircraft is.
+pub fn update_tile_colliders(
+    mut commands: Commands,
+    catalog: Res<SceneryCatalog>,
+    loaded: Res<LoadedTiles>,
+    origin: Res<FloatingOrigin>,
+    reference: Res<GeoReference>,
+    aircraft: Single<&Transform, With<Aircraft>>,
+    mut tiles: Query<&mut LoadedTile>,
+    children: Query<&Children>,
+    colliders: Query<(), With<GltfMeshExtras>>,
+) {
+    for (&index, &entity) in loaded.0.iter() {
+        let Ok(mut tile) = tiles.get_mut(entity) else {
+            continue;
+        };
+        let enabled = catalog.distance_to(index, origin.absolute(aircraft.translation), &reference)
+            < catalog.collider_distance;
+        if enabled == tile.colliders_enabled {
+            continue;
+        }
+        tile.colliders_enabled = enabled;
+        set_colliders_enabled(&mut commands, entity, enabled, &children, &colliders);
+    }
+}
+
+// Colliders are created by the `ScenePropertiesPlugin` once the scene is ready, so a tile that was spawned
+// outside of `collider_distance` has to disable them at that point.
+fn apply_collider_state_when_ready(
+    scene_ready: On<SceneInstanceReady>,
+    mut commands: Commands,
+    tiles: Query<&LoadedTile>,
+    children: Query<&Children>,
+    colliders: Query<(), With<GltfMeshExtras>>,
+) {
+    let Ok(tile) = tiles.get(scene_ready.entity) else {
+        return;
+    };
+    if !tile.colliders_enabled {
+        set_colliders_enabled(
+            &mut commands,
+            scene_ready.entity,
+            false,
+            &children,
+            &colliders,
+        );
+    }
+}
+
+fn set_colliders_enabled(
+    commands: &mut Commands,
+    tile: Entity,
+    enabled: bool,
+    children: &Query<&Children>,
+    colliders: &Query<(), With<GltfMeshExtras>>,
+) {
+    for entity in children.iter_descendants(tile) {
+        if !colliders.contains(entity) {
+            continue;
+        }
+        if enabled {
+            commands.entity(entity).remove::<ColliderDisabled>();
+        } else {
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+    }
+}
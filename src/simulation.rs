@@ -0,0 +1,128 @@
+/*
+The simulation without any rendering: the flight model, engine, fuel, gear, water and damage.
+The wind and the waves it makes are resources here too, the weather sets the wind in the full app
+and headless mode has none.
+
+`FlightModelPlugin` only needs physics and a schedule to run in, so it works under `MinimalPlugins`
+(see headless.rs) as well as in the full app, where lib.rs adds input, cameras, the HUD and the
+aircraft's model on top. `spawn_simulated_aircraft` spawns everything the physics needs except for
+the colliders, which come from the model in the full app and from a box in headless mode.
+*/
+
+use crate::{
+    Aircraft, FlightSimSystems, InputAxis,
+    aircraft_definition::AircraftDefinition,
+    aircraft_mechanics::{
+        Engine, FlightData, Wind, aircraft_mechanics, update_engine, update_flight_data,
+        update_turbulence,
+    },
+    damage::{
+        Damage, Impact, PreviousVelocity, apply_impacts, detect_impacts,
+        enable_aircraft_collision_events, track_previous_velocity,
+    },
+    doors::{Doors, operate_doors},
+    floating_origin::FloatingOrigin,
+    fuel::consume_fuel,
+    gear::{LandingGear, detect_weight_on_wheels, gear_drag, operate_gear, update_ground_friction},
+    high_lift::{HighLiftDevices, high_lift_forces, operate_high_lift_devices},
+    input::ControlActions,
+    preflight::{LoadingPlan, Preflight},
+    water::{WaterContact, WaterImpact, WaterWaves, update_water_waves, water_forces},
+    weight_and_balance::{mass_properties, update_mass_properties},
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+// Everything that applies forces to the aircraft runs in `FlightSimSystems::FlightModel`, after
+// the input and before the physics step.
+pub struct FlightModelPlugin;
+
+impl Plugin for FlightModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputAxis {
+            pitch: 0.,
+            yaw: 0.,
+            roll: 0.,
+            throttle: 1.,
+        })
+        .insert_resource(ControlActions::default())
+        .init_resource::<Wind>()
+        .init_resource::<WaterWaves>()
+        .init_resource::<FloatingOrigin>()
+        .add_message::<WaterImpact>()
+        .add_message::<Impact>()
+        .add_observer(enable_aircraft_collision_events)
+        .add_systems(
+            Update,
+            (
+                (
+                    (update_turbulence, update_flight_data).chain(),
+                    operate_high_lift_devices,
+                    operate_doors,
+                    (
+                        detect_weight_on_wheels,
+                        operate_gear,
+                        update_ground_friction,
+                    )
+                        .chain(),
+                    (update_engine, consume_fuel, aircraft_mechanics).chain(),
+                    (high_lift_forces, gear_drag),
+                )
+                    .chain()
+                    .run_if(simulation_running),
+                update_mass_properties,
+                (update_water_waves, water_forces).chain(),
+                (detect_impacts, apply_impacts, track_previous_velocity).chain(),
+            )
+                .in_set(FlightSimSystems::FlightModel),
+        );
+    }
+}
+
+// The preflight screen stops the flight model. In the full app, it also only runs while
+// `GameState::InFlight`. Headless mode has neither.
+pub fn simulation_running(preflight: Option<Res<Preflight>>) -> bool {
+    preflight.is_none_or(|preflight| !preflight.open)
+}
+
+pub fn spawn_simulated_aircraft(
+    commands: &mut Commands,
+    definition: &AircraftDefinition,
+    plan: &LoadingPlan,
+    transform: Transform,
+) -> Entity {
+    let tanks = plan.fuel.clone();
+    let payload = plan.payload.clone();
+    let (mass, center_of_mass) = mass_properties(definition, &tanks, &payload);
+
+    commands
+        .spawn((
+            Aircraft,
+            RigidBody::Dynamic,
+            transform,
+            (
+                Mass(mass),
+                CenterOfMass(center_of_mass),
+                // Mass comes from the definition, fuel and payload, not from the colliders.
+                NoAutoMass,
+                NoAutoCenterOfMass,
+            ),
+            (
+                WaterContact::default(),
+                Damage::default(),
+                PreviousVelocity::default(),
+            ),
+            Engine::new(&definition.engine),
+            (
+                FlightData::default(),
+                HighLiftDevices::default(),
+                LandingGear::default(),
+                // Set by the gear.
+                Friction::default(),
+                Doors::default(),
+            ),
+            tanks,
+            payload,
+        ))
+        .id()
+}
@@ -0,0 +1,316 @@
+/*
+Sound.
+
+The files come from the `sounds` bank in the aircraft definition. The engine, wind, stall horn and
+the gear and flap motors are loops that start with the flight and are pitched and faded every frame:
+
+- The engine's pitch follows the RPM (the file is recorded at `engine_rpm`), and it gets louder with
+  more throttle.
+- The wind grows with the airspeed, up to `wind_full_speed`.
+- The stall horn sounds in the air above `stall_warning_angle` degrees angle of attack.
+- The motors run while the gear or the flaps are moving.
+
+Touchdowns screech the tires, louder with the sink rate, and crashes, collapsing gear and ditching
+play the crash sound.
+
+In the cockpit the engine and wind are muffled and the horn and motors are clear. Outside, the horn
+and motors are barely heard, everything gets quieter with the camera's distance from the aircraft,
+and the pitch shifts with how fast the aircraft and the camera move towards or away from each other.
+That's mostly heard in replays, where the free and flyby cameras stay put while the aircraft passes.
+Replays have no air data, so there the wind and the stall horn go by the recorded velocity. The
+loops are paused whenever the game isn't flying or replaying.
+*/
+
+use crate::{
+    Aircraft, FlightSimSystems, FollowCamera, GameState, InputAxis,
+    aircraft_definition::{AircraftDefinition, Part},
+    aircraft_mechanics::{Engine, FlightData},
+    camera::CameraSettings,
+    damage::{Crashed, Impact, ImpactKind},
+    floating_origin::FloatingOrigin,
+    gear::LandingGear,
+    high_lift::HighLiftDevices,
+    loading::FlightStarting,
+    replay::{Replay, follow_camera_active},
+    water::WaterImpact,
+};
+use avian3d::prelude::*;
+use bevy::{audio::Volume, math::DVec3, prelude::*};
+
+const SPEED_OF_SOUND: f32 = 343.;
+// Outside, sounds are at full volume up to this distance and fall off beyond it.
+const REFERENCE_DISTANCE: f32 = 15.;
+// Sink rates in m/s: slower touchdowns don't screech, from `LOUD_TOUCHDOWN` it's at full volume.
+const MIN_SCREECH_SINK_RATE: f32 = 0.3;
+const LOUD_TOUCHDOWN: f32 = 3.;
+
+// How loud each sound is, before the falloff with distance.
+struct Mix {
+    engine: f32,
+    wind: f32,
+    stall_horn: f32,
+    motors: f32,
+    impacts: f32,
+}
+
+const COCKPIT_MIX: Mix = Mix {
+    engine: 0.5,
+    wind: 0.4,
+    stall_horn: 0.8,
+    motors: 0.5,
+    impacts: 0.8,
+};
+
+const EXTERNAL_MIX: Mix = Mix {
+    engine: 1.,
+    wind: 0.6,
+    stall_horn: 0.,
+    motors: 0.15,
+    impacts: 1.,
+};
+
+#[derive(Component, Clone, Copy)]
+enum LoopedSound {
+    Engine,
+    Wind,
+    StallHorn,
+    GearMotor,
+    FlapMotor,
+}
+
+// Where the sounds are heard from.
+#[derive(Resource, Default)]
+struct Listener {
+    cockpit: bool,
+    // Volume from the distance to the aircraft, 1 in the cockpit.
+    attenuation: f32,
+    // Pitch from the doppler effect.
+    doppler: f32,
+    // Absolute position of the camera last frame, for its velocity.
+    last_camera: Option<DVec3>,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Listener>()
+            .add_systems(
+                OnEnter(GameState::InFlight),
+                (
+                    spawn_sounds.run_if(resource_exists::<FlightStarting>),
+                    resume_sounds,
+                ),
+            )
+            .add_systems(OnEnter(GameState::Replay), resume_sounds)
+            .add_systems(OnExit(GameState::InFlight), pause_sounds)
+            .add_systems(OnExit(GameState::Replay), pause_sounds)
+            .add_systems(
+                Update,
+                (
+                    (update_listener, update_looped_sounds)
+                        .run_if(in_state(GameState::InFlight).or(in_state(GameState::Replay))),
+                    play_impact_sounds.run_if(in_state(GameState::InFlight)),
+                )
+                    .chain()
+                    .after(FlightSimSystems::Camera),
+            );
+    }
+}
+
+fn spawn_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definition: Res<AircraftDefinition>,
+) {
+    commands.insert_resource(Listener::default());
+    let bank = &definition.sounds;
+    for (path, sound) in [
+        (&bank.engine, LoopedSound::Engine),
+        (&bank.wind, LoopedSound::Wind),
+        (&bank.stall_horn, LoopedSound::StallHorn),
+        (&bank.gear_motor, LoopedSound::GearMotor),
+        (&bank.flap_motor, LoopedSound::FlapMotor),
+    ] {
+        if let Some(path) = path {
+            commands.spawn((
+                AudioPlayer::new(asset_server.load(path.clone())),
+                PlaybackSettings::LOOP.with_volume(Volume::SILENT),
+                sound,
+                DespawnOnEnter(GameState::MainMenu),
+            ));
+        }
+    }
+}
+
+fn pause_sounds(sinks: Query<&AudioSink, With<LoopedSound>>) {
+    for sink in &sinks {
+        sink.pause();
+    }
+}
+
+fn resume_sounds(sinks: Query<&AudioSink, With<LoopedSound>>) {
+    for sink in &sinks {
+        sink.play();
+    }
+}
+
+// The camera is the follow camera, also in replays, where it may be detached from the aircraft.
+fn update_listener(
+    time: Res<Time>,
+    origin: Res<FloatingOrigin>,
+    camera_settings: Res<CameraSettings>,
+    replay: Option<Res<Replay>>,
+    mut listener: ResMut<Listener>,
+    camera: Single<&GlobalTransform, With<FollowCamera>>,
+    aircraft: Single<(&GlobalTransform, &LinearVelocity), With<Aircraft>>,
+) {
+    let (aircraft_transform, aircraft_velocity) = *aircraft;
+    let camera_position = origin.absolute(camera.translation());
+    let camera_velocity = match listener.last_camera {
+        Some(last) if time.delta_secs() > 0. => {
+            ((camera_position - last) / time.delta_secs_f64()).as_vec3()
+        }
+        _ => Vec3::ZERO,
+    };
+    listener.last_camera = Some(camera_position);
+    // Replays move the aircraft faster or slower than its recorded velocity.
+    let playback = match &replay {
+        Some(replay) if replay.paused => 0.,
+        Some(replay) => replay.speed,
+        None => 1.,
+    };
+
+    listener.cockpit = camera_settings.view == 1 && follow_camera_active(replay);
+    if listener.cockpit {
+        listener.attenuation = 1.;
+        listener.doppler = 1.;
+        return;
+    }
+
+    let offset = aircraft_transform.translation() - camera.translation();
+    listener.attenuation = REFERENCE_DISTANCE / offset.length().max(REFERENCE_DISTANCE);
+    // Positive while the aircraft and the camera move apart. Anything faster than sound is the
+    // camera jumping, e.g. when switching views or when the flyby camera moves on.
+    let line_of_sight = offset.normalize_or_zero();
+    let receding = (aircraft_velocity.0 * playback - camera_velocity).dot(line_of_sight);
+    if camera_velocity.length() < SPEED_OF_SOUND && receding.abs() < SPEED_OF_SOUND {
+        listener.doppler = SPEED_OF_SOUND / (SPEED_OF_SOUND + receding);
+    }
+}
+
+fn update_looped_sounds(
+    input: Res<InputAxis>,
+    definition: Res<AircraftDefinition>,
+    listener: Res<Listener>,
+    replay: Option<Res<Replay>>,
+    aircraft: Single<
+        (
+            &Engine,
+            &FlightData,
+            &GlobalTransform,
+            &LinearVelocity,
+            &LandingGear,
+            &HighLiftDevices,
+            Has<Crashed>,
+        ),
+        With<Aircraft>,
+    >,
+    mut sounds: Query<(&LoopedSound, &mut AudioSink)>,
+) {
+    let (engine, data, transform, velocity, gear, devices, crashed) = *aircraft;
+    let bank = &definition.sounds;
+    let (airspeed, angle_of_attack) = match &replay {
+        Some(_) => {
+            let local = transform.rotation().inverse() * velocity.0;
+            (velocity.length(), (-local.y).atan2(-local.z))
+        }
+        None => (data.airspeed, data.angle_of_attack),
+    };
+    // A paused replay is silent.
+    let paused = replay.is_some_and(|replay| replay.paused);
+    let mix = if listener.cockpit {
+        &COCKPIT_MIX
+    } else {
+        &EXTERNAL_MIX
+    };
+
+    let engine_volume = if crashed {
+        0.
+    } else {
+        (engine.rpm / definition.engine.max_rpm).clamp(0., 1.) * (0.5 + 0.5 * input.throttle)
+    };
+    let wind = (airspeed / bank.wind_full_speed).clamp(0., 1.);
+    let stall_warning = !gear.weight_on_wheels
+        && airspeed > 1.
+        && angle_of_attack.to_degrees() > bank.stall_warning_angle;
+    let gear_moving = gear.position != if gear.down_selected { 1. } else { 0. };
+    let flaps_moving = definition
+        .flaps
+        .detents
+        .get(devices.flap_detent)
+        .is_some_and(|&target| target != devices.flap_angle);
+    let on = |condition: bool, volume: f32| if condition { volume } else { 0. };
+
+    for (sound, mut sink) in &mut sounds {
+        let (volume, speed) = match sound {
+            LoopedSound::Engine => (mix.engine * engine_volume, engine.rpm / bank.engine_rpm),
+            LoopedSound::Wind => (mix.wind * wind * wind, 0.8 + 0.4 * wind),
+            LoopedSound::StallHorn => (on(stall_warning, mix.stall_horn), 1.),
+            LoopedSound::GearMotor => (on(gear_moving, mix.motors), 1.),
+            LoopedSound::FlapMotor => (on(flaps_moving, mix.motors), 1.),
+        };
+        let volume = if paused { 0. } else { volume };
+        sink.set_volume(Volume::Linear(volume * listener.attenuation));
+        sink.set_speed((speed * listener.doppler).max(0.05));
+    }
+}
+
+fn play_impact_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definition: Res<AircraftDefinition>,
+    listener: Res<Listener>,
+    mut impacts: MessageReader<Impact>,
+    mut water_impacts: MessageReader<WaterImpact>,
+) {
+    let bank = &definition.sounds;
+    let mix = if listener.cockpit {
+        &COCKPIT_MIX
+    } else {
+        &EXTERNAL_MIX
+    };
+    let mut play = |path: &Option<String>, volume: f32| {
+        if let Some(path) = path {
+            commands.spawn((
+                AudioPlayer::new(asset_server.load(path.clone())),
+                PlaybackSettings::DESPAWN
+                    .with_volume(Volume::Linear(volume * mix.impacts * listener.attenuation))
+                    .with_speed(listener.doppler),
+                DespawnOnEnter(GameState::MainMenu),
+            ));
+        }
+    };
+
+    for impact in impacts.read() {
+        match impact.kind {
+            ImpactKind::Touchdown | ImpactKind::HardLanding
+                if impact.part == Part::Gear
+                    && !impact.gear_up
+                    && impact.sink_rate > MIN_SCREECH_SINK_RATE =>
+            {
+                play(
+                    &bank.tire_screech,
+                    (impact.sink_rate / LOUD_TOUCHDOWN).clamp(0.2, 1.),
+                );
+            }
+            ImpactKind::GearCollapse | ImpactKind::Crash => play(&bank.crash, 1.),
+            _ => {}
+        }
+    }
+    for impact in water_impacts.read() {
+        if let WaterImpact::Ditched { .. } | WaterImpact::Crashed { .. } = impact {
+            play(&bank.crash, 1.);
+        }
+    }
+}
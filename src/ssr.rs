@@ -1,20 +1,25 @@
-use crate::Settings;
+use crate::{
+    floating_origin::FloatingOrigin,
+    loading::RequiredAssets,
+    terrain::ShoreMap,
+    water::{WATER_HALF_SIZE, WaterWaves, water_mesh},
+};
 use bevy::{
-    anti_alias::fxaa::Fxaa,
+    asset::RenderAssetUsages,
     color::palettes::css::BLACK,
     image::{
         ImageAddressMode, ImageFilterMode, ImageLoaderSettings, ImageSampler,
         ImageSamplerDescriptor,
     },
-    pbr::{
-        DefaultOpaqueRendererMethod, ExtendedMaterial, MaterialExtension, ScreenSpaceReflections,
-    },
+    pbr::{ExtendedMaterial, MaterialExtension},
     prelude::*,
-    render::render_resource::{AsBindGroup, ShaderType},
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureDimension, TextureFormat},
     shader::ShaderRef,
 };
 
-// A custom [`ExtendedMaterial`] that creates animated water ripples.
+// A custom [`ExtendedMaterial`] that creates animated water ripples. With ssr it's rendered deferred
+// and reflects the scene, otherwise forward and it reflects the sky through the camera's
+// environment map (see camera.rs).
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
 pub struct Water {
     // The normal map image.
@@ -27,14 +32,75 @@ pub struct Water {
     // Parameters to the water shader.
     #[uniform(102)]
     settings: WaterSettings,
+
+    // Where the terrain meets the water, see `ShoreMap`.
+    #[texture(103)]
+    #[sampler(104)]
+    shore: Handle<Image>,
 }
 
 impl MaterialExtension for Water {
+    fn vertex_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn prepass_vertex_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn deferred_vertex_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
     fn deferred_fragment_shader() -> ShaderRef {
         SHADER_ASSET_PATH.into()
     }
 }
 
+impl Water {
+    // The waves move with the wind, the ripples get faster and stronger with it.
+    fn update(
+        &mut self,
+        waves: &WaterWaves,
+        time: &Time,
+        origin: &FloatingOrigin,
+        shore: Option<&ShoreMap>,
+    ) {
+        let chop = waves.chop.length();
+        let drift = waves.chop.extend(waves.chop.x).extend(waves.chop.y) * 0.05;
+        let settings = &mut self.settings;
+        settings.octave_vectors = OCTAVE_VECTORS.map(|vector| vector * (0.3 + chop) + drift);
+        settings.octave_strengths = OCTAVE_STRENGTHS * (0.3 + chop);
+        settings.waves = waves.waves;
+        settings.wave_reach = waves.reach;
+        settings.time = vec4(
+            time.elapsed_secs_wrapped(),
+            (time.elapsed_secs_wrapped() - time.delta_secs()).max(0.),
+            0.,
+            0.,
+        );
+
+        let offset = origin.offset().as_vec3().xz();
+        let (shore_corner, shore_size) = match shore {
+            Some(shore) => {
+                self.shore = shore.image.clone();
+                (origin.local(shore.origin.as_dvec3()).xz(), shore.size)
+            }
+            None => (Vec2::ZERO, 0.),
+        };
+        settings.origin = vec4(offset.x, offset.y, shore_corner.x, shore_corner.y);
+        // The uvs repeat every `2 * WATER_HALF_SIZE`, this is the world origin's, in f64 so it
+        // stays precise far from the absolute origin.
+        let uv = origin.offset().xz() / (WATER_HALF_SIZE as f64 * 2.);
+        let uv = (uv - uv.floor()).as_vec2();
+        settings.shore_size = vec4(shore_size, uv.x, uv.y, 0.);
+    }
+}
+
 // Parameters to the water shader.
 #[derive(ShaderType, Debug, Clone)]
 pub struct WaterSettings {
@@ -45,21 +111,28 @@ pub struct WaterSettings {
     octave_scales: Vec4,
     // How high the waves are in each octave.
     octave_strengths: Vec4,
+    // The waves that move the surface and how far from the middle of the mesh they're drawn, see
+    // `WaterWaves`.
+    waves: [Vec4; 4],
+    wave_reach: Vec4,
+    // Time for the waves this frame and the last one, for motion vectors.
+    time: Vec4,
+    // xy: absolute position of the world origin, so the waves stay put when it moves.
+    // zw: north-west corner of the shore map, relative to the world origin.
+    origin: Vec4,
+    // x: size of the shore map, 0 without one.
+    // yz: uv of the world origin, the ripples' uvs come from the absolute position.
+    shore_size: Vec4,
 }
 
-const SHADER_ASSET_PATH: &str = "shaders/water_material.wgsl";
+// These water settings are just random values to create some variety.
+const OCTAVE_VECTORS: [Vec4; 2] = [
+    vec4(0.080, 0.059, 0.073, -0.062),
+    vec4(0.153, 0.138, -0.149, -0.195),
+];
+const OCTAVE_STRENGTHS: Vec4 = vec4(0.16, 0.18, 0.093, 0.044);
 
-pub fn ssr_config(settings: &Settings) -> Option<(ScreenSpaceReflections, Msaa, Fxaa)> {
-    if settings.ssr {
-        Some((
-            ScreenSpaceReflections::default(),
-            Msaa::Off,
-            Fxaa::default(),
-        ))
-    } else {
-        None
-    }
-}
+const SHADER_ASSET_PATH: &str = "shaders/water_material.wgsl";
 
 pub fn spawn_water(
     commands: &mut Commands,
@@ -67,47 +140,76 @@ pub fn spawn_water(
     asset_server: &Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut water_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, Water>>>,
-) {
-    // ssr water plane
-    commands.spawn((
-        Mesh3d(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(100.0)))),
-        MeshMaterial3d(water_materials.add(ExtendedMaterial {
-            base: StandardMaterial {
-                base_color: BLACK.into(),
-                perceptual_roughness: 0.,
+    images: &mut Assets<Image>,
+    required: &mut RequiredAssets,
+) -> Entity {
+    let normals = asset_server.load_with_settings::<Image, ImageLoaderSettings>(
+        "textures/water_normals.png",
+        |settings| {
+            settings.is_srgb = false;
+            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                address_mode_u: ImageAddressMode::Repeat,
+                address_mode_v: ImageAddressMode::Repeat,
+                mag_filter: ImageFilterMode::Linear,
+                min_filter: ImageFilterMode::Linear,
                 ..default()
-            },
-            extension: Water {
-                normals: asset_server.load_with_settings::<Image, ImageLoaderSettings>(
-                    "textures/water_normals.png",
-                    |settings| {
-                        settings.is_srgb = false;
-                        settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
-                            address_mode_u: ImageAddressMode::Repeat,
-                            address_mode_v: ImageAddressMode::Repeat,
-                            mag_filter: ImageFilterMode::Linear,
-                            min_filter: ImageFilterMode::Linear,
-                            ..default()
-                        });
+            });
+        },
+    );
+    required.add(normals.clone());
+
+    // No foam until there's a shore map.
+    let no_shore = images.add(Image::new_fill(
+        Extent3d::default(),
+        TextureDimension::D2,
+        &[0],
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    ));
+
+    // ssr water plane
+    commands
+        .spawn((
+            Mesh3d(meshes.add(water_mesh())),
+            MeshMaterial3d(water_materials.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: BLACK.into(),
+                    perceptual_roughness: 0.,
+                    ..default()
+                },
+                extension: Water {
+                    normals,
+                    settings: WaterSettings {
+                        octave_vectors: OCTAVE_VECTORS,
+                        octave_scales: vec4(1.0, 2.1, 7.9, 14.9) * 5.0,
+                        octave_strengths: OCTAVE_STRENGTHS,
+                        waves: [Vec4::ZERO; 4],
+                        wave_reach: Vec4::ONE,
+                        time: Vec4::ZERO,
+                        origin: Vec4::ZERO,
+                        shore_size: Vec4::ZERO,
                     },
-                ),
-                // These water settings are just random values to create some
-                // variety.
-                settings: WaterSettings {
-                    octave_vectors: [
-                        vec4(0.080, 0.059, 0.073, -0.062),
-                        vec4(0.153, 0.138, -0.149, -0.195),
-                    ],
-                    octave_scales: vec4(1.0, 2.1, 7.9, 14.9) * 5.0,
-                    octave_strengths: vec4(0.16, 0.18, 0.093, 0.044),
+                    shore: no_shore,
                 },
-            },
-        })),
-        Transform::from_scale(Vec3::splat(100.0)),
-    ));
+            })),
+        ))
+        .id()
 }
 
-pub fn insert_ssr_resources(app: &mut App) {
-    app.insert_resource(DefaultOpaqueRendererMethod::deferred())
-        .add_plugins(MaterialPlugin::<ExtendedMaterial<StandardMaterial, Water>>::default());
+// Every frame, since the waves move with the time.
+pub fn update_water_material(
+    time: Res<Time>,
+    waves: Res<WaterWaves>,
+    origin: Res<FloatingOrigin>,
+    shore: Option<Res<ShoreMap>>,
+    water: Query<&MeshMaterial3d<ExtendedMaterial<StandardMaterial, Water>>>,
+    mut materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, Water>>>,
+) {
+    for material in &water {
+        if let Some(material) = materials.get_mut(material) {
+            material
+                .extension
+                .update(&waves, &time, &origin, shore.as_deref());
+        }
+    }
 }
@@ -0,0 +1,438 @@
+/*
+Telemetry over UDP, for external dashboards and motion rigs.
+
+When `telemetry.enabled` is set in settings.json, the aircraft's state is sent to
+`telemetry.address` every fixed tick, either as JSON (the fields of `TelemetryPacket`) or in the
+little endian binary format:
+
+    magic "BFST", u32 version (1),
+    f64 time (s), f64 latitude, f64 longitude (degrees), f64 altitude (m),
+    f32 heading, pitch, roll (degrees),
+    f32 roll rate, pitch rate, yaw rate (rad/s, positive rolling right, pitching up, yawing right),
+    f32 acceleration x, y, z (m/s², body axes: x right, y up, z back), f32 g load,
+    f32 airspeed, vertical speed (m/s), angle of attack, sideslip (degrees),
+    f32 pitch, roll, yaw, throttle input, f32 engine RPM
+
+With `receive_controls` set, packets sent to `listen_address` override the pitch, roll, yaw and
+throttle inputs for half a second: JSON like {"pitch": 0.1, "roll": 0, "yaw": 0, "throttle": 0.8},
+or "BFSC" followed by the four values as f32. It listens on localhost by default, so other
+machines can't fly the aircraft unless it's bound to another interface.
+*/
+
+use crate::{
+    Aircraft, FlightSimSystems, InputAxis,
+    aircraft_mechanics::{Engine, FlightData},
+    geodesy::AircraftLocation,
+    input,
+};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::net::UdpSocket;
+
+const TELEMETRY_MAGIC: &[u8; 4] = b"BFST";
+const CONTROLS_MAGIC: &[u8; 4] = b"BFSC";
+const VERSION: u32 = 1;
+// Seconds remote controls stay in effect after the last packet.
+const CONTROLS_TIMEOUT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TelemetryFormat {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetrySettings {
+    pub enabled: bool,
+    pub address: String,
+    pub format: TelemetryFormat,
+    pub receive_controls: bool,
+    pub listen_address: String,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:49000".to_string(),
+            format: TelemetryFormat::Json,
+            receive_controls: false,
+            listen_address: "127.0.0.1:49001".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryPacket {
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub heading: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    // rad/s, positive rolling right, pitching up and yawing right.
+    pub roll_rate: f32,
+    pub pitch_rate: f32,
+    pub yaw_rate: f32,
+    // m/s², body axes (x right, y up, z back)
+    pub acceleration: [f32; 3],
+    pub g_load: f32,
+    pub airspeed: f32,
+    pub vertical_speed: f32,
+    pub angle_of_attack: f32,
+    pub sideslip: f32,
+    pub input_pitch: f32,
+    pub input_roll: f32,
+    pub input_yaw: f32,
+    pub input_throttle: f32,
+    pub rpm: f32,
+}
+
+impl TelemetryPacket {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(128);
+        buffer.extend_from_slice(TELEMETRY_MAGIC);
+        buffer.extend_from_slice(&VERSION.to_le_bytes());
+        for value in [self.time, self.latitude, self.longitude, self.altitude] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let [ax, ay, az] = self.acceleration;
+        for value in [
+            self.heading,
+            self.pitch,
+            self.roll,
+            self.roll_rate,
+            self.pitch_rate,
+            self.yaw_rate,
+            ax,
+            ay,
+            az,
+            self.g_load,
+            self.airspeed,
+            self.vertical_speed,
+            self.angle_of_attack,
+            self.sideslip,
+            self.input_pitch,
+            self.input_roll,
+            self.input_yaw,
+            self.input_throttle,
+            self.rpm,
+        ] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ControlPacket {
+    pub pitch: f32,
+    pub roll: f32,
+    pub yaw: f32,
+    pub throttle: f32,
+}
+
+impl ControlPacket {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let packet: Self = if let Some(values) = bytes.strip_prefix(CONTROLS_MAGIC) {
+            let mut values = values
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()));
+            Self {
+                pitch: values.next()?,
+                roll: values.next()?,
+                yaw: values.next()?,
+                throttle: values.next()?,
+            }
+        } else {
+            serde_json::from_slice(bytes).ok()?
+        };
+        // Clamping lets NaN through, and it would end up in the forces.
+        [packet.pitch, packet.roll, packet.yaw, packet.throttle]
+            .iter()
+            .all(|value| value.is_finite())
+            .then_some(packet)
+    }
+}
+
+#[derive(Resource)]
+struct Telemetry {
+    socket: UdpSocket,
+    settings: TelemetrySettings,
+    // Last control packet and when it arrived.
+    controls: Option<(ControlPacket, f64)>,
+}
+
+pub struct TelemetryPlugin {
+    pub settings: TelemetrySettings,
+}
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        let bind_address = if self.settings.receive_controls {
+            self.settings.listen_address.as_str()
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = match UdpSocket::bind(bind_address) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Telemetry disabled, couldn't bind to {bind_address}: {error}");
+                return;
+            }
+        };
+        if let Err(error) = socket.set_nonblocking(true) {
+            error!("Telemetry disabled, couldn't make the socket non-blocking: {error}");
+            return;
+        }
+
+        app.insert_resource(Telemetry {
+            socket,
+            settings: self.settings.clone(),
+            controls: None,
+        })
+        .add_systems(FixedUpdate, send_telemetry)
+        .add_systems(
+            Update,
+            receive_controls
+                .after(input::input_system)
+                .in_set(FlightSimSystems::Input),
+        );
+    }
+}
+
+fn send_telemetry(
+    time: Res<Time>,
+    telemetry: Res<Telemetry>,
+    location: Res<AircraftLocation>,
+    input: Res<InputAxis>,
+    aircraft: Single<(&GlobalTransform, &AngularVelocity, &FlightData, &Engine), With<Aircraft>>,
+) {
+    let (transform, angular_velocity, data, engine) = *aircraft;
+    let rotation = transform.rotation();
+    let forward = rotation * Vec3::NEG_Z;
+    let right = rotation * Vec3::X;
+    let up = rotation * Vec3::Y;
+    let rates = rotation.inverse() * angular_velocity.0;
+
+    let packet = TelemetryPacket {
+        time: time.elapsed_secs_f64(),
+        latitude: location.geodetic.latitude,
+        longitude: location.geodetic.longitude,
+        altitude: location.geodetic.altitude,
+        heading: location.heading_true,
+        // Normalizing can leave y a hair above 1, and asin would return NaN.
+        pitch: forward.y.clamp(-1., 1.).asin().to_degrees(),
+        roll: (-right.y).atan2(up.y).to_degrees(),
+        roll_rate: -rates.z,
+        pitch_rate: rates.x,
+        yaw_rate: -rates.y,
+        acceleration: (rotation.inverse() * data.acceleration).to_array(),
+        g_load: data.g_load,
+        airspeed: data.airspeed,
+        vertical_speed: data.vertical_speed,
+        angle_of_attack: data.angle_of_attack.to_degrees(),
+        sideslip: data.sideslip.to_degrees(),
+        input_pitch: input.pitch,
+        input_roll: input.roll,
+        input_yaw: input.yaw,
+        input_throttle: input.throttle,
+        rpm: engine.rpm,
+    };
+
+    let bytes = match telemetry.settings.format {
+        TelemetryFormat::Json => serde_json::to_vec(&packet).unwrap(),
+        TelemetryFormat::Binary => packet.to_bytes(),
+    };
+    // Nobody listening is fine, telemetry is fire and forget.
+    let _ = telemetry
+        .socket
+        .send_to(&bytes, &telemetry.settings.address);
+}
+
+fn receive_controls(
+    time: Res<Time>,
+    mut telemetry: ResMut<Telemetry>,
+    mut input: ResMut<InputAxis>,
+) {
+    if !telemetry.settings.receive_controls {
+        return;
+    }
+
+    let mut buffer = [0; 1024];
+    while let Ok(length) = telemetry.socket.recv(&mut buffer) {
+        match ControlPacket::parse(&buffer[..length]) {
+            Some(controls) => telemetry.controls = Some((controls, time.elapsed_secs_f64())),
+            None => warn!("Ignoring malformed control packet"),
+        }
+    }
+
+    let Some((controls, received)) = telemetry.controls else {
+        return;
+    };
+    if time.elapsed_secs_f64() - received > CONTROLS_TIMEOUT {
+        telemetry.controls = None;
+        return;
+    }
+    input.pitch = controls.pitch.clamp(-1., 1.);
+    input.roll = controls.roll.clamp(-1., 1.);
+    input.yaw = controls.yaw.clamp(-1., 1.);
+    input.throttle = controls.throttle.clamp(0., 1.);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aircraft_definition::AircraftDefinition,
+        headless::{TIMESTEP, headless_app, spawn_headless_aircraft},
+    };
+    use std::time::Duration;
+
+    fn local_socket() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        socket
+    }
+
+    // A headless flight with telemetry going to `listener` and controls taken on a free port, which
+    // is returned with the app.
+    fn telemetry_app(format: TelemetryFormat, listener: &UdpSocket) -> (App, String) {
+        let listen_address = local_socket().local_addr().unwrap().to_string();
+        let mut app = headless_app(AircraftDefinition::fetch("ferris"));
+        app.insert_resource(AircraftLocation::default())
+            .add_plugins(TelemetryPlugin {
+                settings: TelemetrySettings {
+                    enabled: true,
+                    address: listener.local_addr().unwrap().to_string(),
+                    format,
+                    receive_controls: true,
+                    listen_address: listen_address.clone(),
+                },
+            });
+        app.finish();
+        app.cleanup();
+        spawn_headless_aircraft(&mut app, Transform::from_xyz(0., 500., 0.));
+        (app, listen_address)
+    }
+
+    fn inputs(app: &App) -> [f32; 4] {
+        let input = app.world().resource::<InputAxis>();
+        [input.pitch, input.roll, input.yaw, input.throttle]
+    }
+
+    fn binary_controls(values: [f32; 4]) -> Vec<u8> {
+        let mut bytes = CONTROLS_MAGIC.to_vec();
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn binary_telemetry_is_sent_every_tick() {
+        let listener = local_socket();
+        let (mut app, _) = telemetry_app(TelemetryFormat::Binary, &listener);
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let mut buffer = [0; 1024];
+        let length = listener.recv(&mut buffer).unwrap();
+        let bytes = &buffer[..length];
+        // Header, four f64 and nineteen f32.
+        assert_eq!(length, 8 + 4 * 8 + 19 * 4);
+        assert_eq!(&bytes[..4], TELEMETRY_MAGIC);
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), VERSION);
+        // Full throttle is the headless default, the input comes right before the RPM.
+        assert_eq!(
+            f32::from_le_bytes(bytes[length - 8..length - 4].try_into().unwrap()),
+            1.
+        );
+        let rpm = f32::from_le_bytes(bytes[length - 4..].try_into().unwrap());
+        assert!(rpm > 0.);
+
+        // The next tick sends the next packet.
+        app.update();
+        let length = listener.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..4], TELEMETRY_MAGIC);
+        assert_eq!(length, 8 + 4 * 8 + 19 * 4);
+    }
+
+    #[test]
+    fn json_telemetry_and_remote_controls() {
+        let listener = local_socket();
+        let (mut app, listen_address) = telemetry_app(TelemetryFormat::Json, &listener);
+        let controller = local_socket();
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let mut buffer = [0; 2048];
+        let length = listener.recv(&mut buffer).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer[..length]).unwrap();
+        assert_eq!(json["input_throttle"], 1.);
+        assert!(json["altitude"].as_f64().is_some());
+
+        // Out of range values are clamped.
+        controller
+            .send_to(&binary_controls([0.25, -2., 0.5, 0.4]), &listen_address)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        app.update();
+        assert_eq!(inputs(&app), [0.25, -1., 0.5, 0.4]);
+
+        // A broken packet leaves the last controls in place.
+        controller
+            .send_to(&binary_controls([f32::NAN, 0., 0., 1.]), &listen_address)
+            .unwrap();
+        controller
+            .send_to(br#"{"pitch": 0.5, "roll": 0"#, &listen_address)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        app.update();
+        assert_eq!(inputs(&app), [0.25, -1., 0.5, 0.4]);
+        assert!(app.world().resource::<Telemetry>().controls.is_some());
+
+        // Without new packets the controls are handed back.
+        for _ in 0..(CONTROLS_TIMEOUT / TIMESTEP).ceil() as usize + 2 {
+            app.update();
+        }
+        assert!(app.world().resource::<Telemetry>().controls.is_none());
+    }
+
+    #[test]
+    fn control_packets_parse() {
+        let controls = ControlPacket::parse(&binary_controls([0.1, -0.2, 0.3, 0.9])).unwrap();
+        assert_eq!(
+            [
+                controls.pitch,
+                controls.roll,
+                controls.yaw,
+                controls.throttle
+            ],
+            [0.1, -0.2, 0.3, 0.9]
+        );
+        let controls =
+            ControlPacket::parse(br#"{"pitch": 0.5, "roll": 0, "yaw": -1, "throttle": 1}"#)
+                .unwrap();
+        assert_eq!(
+            [
+                controls.pitch,
+                controls.roll,
+                controls.yaw,
+                controls.throttle
+            ],
+            [0.5, 0., -1., 1.]
+        );
+        // Too short to hold the four values.
+        assert!(ControlPacket::parse(b"BFSC\x00").is_none());
+        // NaN and infinity get past the clamping, so they're rejected.
+        assert!(ControlPacket::parse(&binary_controls([0., f32::NAN, 0., 1.])).is_none());
+        assert!(ControlPacket::parse(&binary_controls([0., 0., 0., f32::INFINITY])).is_none());
+    }
+}
@@ -0,0 +1,582 @@
+/*
+Heightmap terrain.
+
+The terrain is configured in assets/terrain/terrain.json. Heights come from one of:
+- Procedural { seed }: fractal value noise, so the terrain works without any extra files
+- Png(path): a 16-bit grayscale png inside assets/ (8-bit works too, but looks terraced)
+- Raw { path }: little-endian u16 samples, `size` * `size` of them
+
+The heightmap is cut into square chunks of `chunk_size` quads. Every chunk gets its own
+`Collider::heightfield` at full resolution, while the visible mesh drops detail with distance
+(`lod_distances`). Skirts hang down from the chunk borders so there are no cracks between chunks
+with different LODs.
+
+The terrain is colored by a splat map (red = sand, green = grass, blue = rock, alpha = snow).
+If `splat_map` isn't set, one is generated from height and slope. The water gets a map of where the
+terrain meets it, for foam along the shore.
+*/
+
+use crate::{Aircraft, floating_origin::FloatingOrigin, loading::RequiredAssets, water::WaterBody};
+use avian3d::prelude::*;
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{ImageLoaderSettings, ImageSampler},
+    mesh::{Indices, PrimitiveTopology},
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureDimension, TextureFormat},
+    shader::ShaderRef,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const SHADER_ASSET_PATH: &str = "shaders/terrain_material.wgsl";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum HeightmapSource {
+    Procedural { seed: u32 },
+    Png(String),
+    Raw { path: String },
+}
+
+#[derive(Resource, Debug, Serialize, Deserialize)]
+pub struct TerrainSettings {
+    pub enabled: bool,
+    pub source: HeightmapSource,
+    // Samples per side, should be a multiple of `chunk_size` plus one.
+    pub size: u32,
+    // Absolute position of the terrain's north-west corner.
+    pub origin: Vec3,
+    // Meters between two samples.
+    pub spacing: f32,
+    // Height in meters of the highest possible sample.
+    pub height_scale: f32,
+    // Quads per chunk side at full detail, must be a power of two.
+    pub chunk_size: u32,
+    // Every entry is the distance at which the next lower level of detail kicks in.
+    pub lod_distances: Vec<f32>,
+    pub skirt_depth: f32,
+    pub splat_map: Option<String>,
+}
+
+impl TerrainSettings {
+    pub fn fetch() -> Self {
+        let json_data = fs::read_to_string("assets/terrain/terrain.json").unwrap();
+        let settings: Self = serde_json::from_str(&json_data).unwrap();
+        settings
+    }
+
+    fn chunk_world_size(&self) -> f32 {
+        self.chunk_size as f32 * self.spacing
+    }
+
+    fn chunk_center(&self, coord: UVec2) -> Vec3 {
+        let half = self.chunk_world_size() / 2.;
+        self.origin
+            + Vec3::new(
+                coord.x as f32 * self.chunk_world_size() + half,
+                0.,
+                coord.y as f32 * self.chunk_world_size() + half,
+            )
+    }
+
+    fn lod_for_distance(&self, distance: f32) -> u32 {
+        let lod = self
+            .lod_distances
+            .iter()
+            .take_while(|&&d| distance > d)
+            .count() as u32;
+        // A step can't be wider than the chunk itself.
+        lod.min(self.chunk_size.trailing_zeros())
+    }
+}
+
+// Normalized (0..1) heights, row by row from north to south.
+#[derive(Resource)]
+pub struct Heightmap {
+    size: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    // `size` is the terrain's, the image has to match it.
+    pub fn from_image(image: &Image, size: u32) -> Result<Self, String> {
+        if image.width() != size || image.height() != size {
+            return Err(format!(
+                "Heightmap has the wrong size, expected {size}x{size} pixels, found {}x{}",
+                image.width(),
+                image.height()
+            ));
+        }
+        let heights = match image.texture_descriptor.format {
+            TextureFormat::R16Uint => image
+                .data
+                .as_ref()
+                .ok_or("Heightmap has no pixel data")?
+                .chunks_exact(2)
+                .map(|b| u16::from_ne_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+                .collect(),
+            _ => (0..size * size)
+                .map(|i| {
+                    image
+                        .get_color_at(i % size, i / size)
+                        .map(|color| color.to_linear().red)
+                        .unwrap_or(0.)
+                })
+                .collect(),
+        };
+        Ok(Self { size, heights })
+    }
+
+    pub fn from_raw(bytes: &[u8], size: u32) -> Result<Self, String> {
+        let expected = (size * size * 2) as usize;
+        if bytes.len() != expected {
+            return Err(format!(
+                "Raw heightmap has the wrong size, expected {size}x{size} 16-bit samples \
+                 ({expected} bytes), found {} bytes",
+                bytes.len()
+            ));
+        }
+        let heights = bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+            .collect();
+        Ok(Self { size, heights })
+    }
+
+    pub fn procedural(size: u32, seed: u32) -> Self {
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for z in 0..size {
+            for x in 0..size {
+                let mut frequency = 1. / 96.;
+                let mut amplitude = 0.5;
+                let mut height: f32 = 0.;
+                for octave in 0..6 {
+                    height += value_noise(
+                        x as f32 * frequency,
+                        z as f32 * frequency,
+                        seed.wrapping_add(octave),
+                    ) * amplitude;
+                    frequency *= 2.;
+                    amplitude *= 0.5;
+                }
+                // Flatten the valleys a bit.
+                heights.push(height.powf(1.6));
+            }
+        }
+        Self { size, heights }
+    }
+
+    fn get(&self, x: u32, z: u32) -> f32 {
+        let x = x.min(self.size - 1);
+        let z = z.min(self.size - 1);
+        self.heights[(z * self.size + x) as usize]
+    }
+
+    fn normal(&self, x: u32, z: u32, settings: &TerrainSettings) -> Vec3 {
+        let left = self.get(x.saturating_sub(1), z);
+        let right = self.get(x + 1, z);
+        let up = self.get(x, z.saturating_sub(1));
+        let down = self.get(x, z + 1);
+        Vec3::new(
+            (left - right) * settings.height_scale,
+            2. * settings.spacing,
+            (up - down) * settings.height_scale,
+        )
+        .normalize()
+    }
+}
+
+fn hash(x: i32, z: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(374_761_393)
+        ^ (z as u32).wrapping_mul(668_265_263)
+        ^ seed.wrapping_mul(2_246_822_519);
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+fn value_noise(x: f32, z: f32, seed: u32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (ix, iz) = (x0 as i32, z0 as i32);
+    // Smoothstep between the lattice points.
+    let tx = x - x0;
+    let tz = z - z0;
+    let sx = tx * tx * (3. - 2. * tx);
+    let sz = tz * tz * (3. - 2. * tz);
+    let top = hash(ix, iz, seed).lerp(hash(ix + 1, iz, seed), sx);
+    let bottom = hash(ix, iz + 1, seed).lerp(hash(ix + 1, iz + 1, seed), sx);
+    top.lerp(bottom, sz)
+}
+
+// A custom [`ExtendedMaterial`] that colors the terrain using a splat map.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TerrainSplat {
+    #[texture(100)]
+    #[sampler(101)]
+    splat_map: Handle<Image>,
+
+    #[uniform(102)]
+    layers: TerrainLayers,
+}
+
+impl MaterialExtension for TerrainSplat {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+#[derive(ShaderType, Debug, Clone)]
+pub struct TerrainLayers {
+    // Sand, grass, rock, snow
+    colors: [Vec4; 4],
+}
+
+pub type TerrainMaterial = ExtendedMaterial<StandardMaterial, TerrainSplat>;
+
+#[derive(Component)]
+pub struct TerrainChunk {
+    coord: UVec2,
+    lod: u32,
+}
+
+// Handle of a heightmap png that is still loading.
+#[derive(Resource)]
+pub struct PendingHeightmap(Handle<Image>);
+
+pub fn load_heightmap(
+    mut commands: Commands,
+    settings: Res<TerrainSettings>,
+    asset_server: Res<AssetServer>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    match &settings.source {
+        HeightmapSource::Procedural { seed } => {
+            commands.insert_resource(Heightmap::procedural(settings.size, *seed));
+        }
+        HeightmapSource::Png(path) => {
+            let handle = asset_server.load_with_settings::<Image, ImageLoaderSettings>(
+                path.clone(),
+                |settings| {
+                    settings.is_srgb = false;
+                    settings.asset_usage = RenderAssetUsages::MAIN_WORLD;
+                },
+            );
+            required.add(handle.clone());
+            commands.insert_resource(PendingHeightmap(handle));
+        }
+        HeightmapSource::Raw { path } => {
+            let bytes = match fs::read(format!("assets/{path}")) {
+                Ok(bytes) => bytes,
+                Err(error) => {
+                    required.missing(path, error);
+                    return;
+                }
+            };
+            match Heightmap::from_raw(&bytes, settings.size) {
+                Ok(heightmap) => commands.insert_resource(heightmap),
+                Err(error) => required.missing(path, error),
+            }
+        }
+    }
+}
+
+pub fn finish_loading_heightmap(
+    mut commands: Commands,
+    settings: Res<TerrainSettings>,
+    pending: Option<Res<PendingHeightmap>>,
+    images: Res<Assets<Image>>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+    let Some(image) = images.get(&pending.0) else {
+        return;
+    };
+    match Heightmap::from_image(image, settings.size) {
+        Ok(heightmap) => commands.insert_resource(heightmap),
+        Err(error) => {
+            let path = pending
+                .0
+                .path()
+                .map_or(String::new(), |path| path.to_string());
+            required.missing(&path, error);
+        }
+    }
+    commands.remove_resource::<PendingHeightmap>();
+}
+
+pub fn spawn_terrain(
+    mut commands: Commands,
+    settings: Res<TerrainSettings>,
+    heightmap: Res<Heightmap>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    origin: Res<FloatingOrigin>,
+) {
+    let splat_map = match &settings.splat_map {
+        Some(path) => asset_server.load_with_settings::<Image, ImageLoaderSettings>(
+            path.clone(),
+            |settings| {
+                settings.is_srgb = false;
+                settings.sampler = ImageSampler::linear();
+            },
+        ),
+        None => images.add(generate_splat_map(&heightmap, &settings)),
+    };
+
+    let material = materials.add(ExtendedMaterial {
+        base: StandardMaterial {
+            perceptual_roughness: 0.9,
+            ..default()
+        },
+        extension: TerrainSplat {
+            splat_map,
+            layers: TerrainLayers {
+                colors: [
+                    vec4(0.76, 0.70, 0.50, 1.0),
+                    vec4(0.25, 0.40, 0.15, 1.0),
+                    vec4(0.40, 0.37, 0.35, 1.0),
+                    vec4(0.95, 0.95, 0.97, 1.0),
+                ],
+            },
+        },
+    });
+
+    let chunks = (heightmap.size - 1) / settings.chunk_size;
+    for z in 0..chunks {
+        for x in 0..chunks {
+            let coord = UVec2::new(x, z);
+            let lod = settings.lod_distances.len() as u32;
+            commands.spawn((
+                TerrainChunk { coord, lod },
+                Mesh3d(meshes.add(build_chunk_mesh(&heightmap, &settings, coord, lod))),
+                MeshMaterial3d(material.clone()),
+                RigidBody::Static,
+                build_chunk_collider(&heightmap, &settings, coord),
+                Transform::from_translation(origin.local(settings.chunk_center(coord).as_dvec3())),
+            ));
+        }
+    }
+}
+
+pub fn update_terrain_lod(
+    settings: Res<TerrainSettings>,
+    heightmap: Res<Heightmap>,
+    aircraft: Single<&Transform, With<Aircraft>>,
+    mut chunks: Query<(&mut TerrainChunk, &mut Mesh3d, &Transform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (mut chunk, mut mesh, transform) in &mut chunks {
+        // Chunks get moved by the floating origin, so their transform is the local chunk center.
+        let center = transform.translation;
+        let distance = (center.xz().distance(aircraft.translation.xz())
+            - settings.chunk_world_size() / 2.)
+            .max(0.);
+        let lod = settings.lod_for_distance(distance);
+        if lod != chunk.lod {
+            chunk.lod = lod;
+            mesh.0 = meshes.add(build_chunk_mesh(&heightmap, &settings, chunk.coord, lod));
+        }
+    }
+}
+
+fn build_chunk_mesh(
+    heightmap: &Heightmap,
+    settings: &TerrainSettings,
+    coord: UVec2,
+    lod: u32,
+) -> Mesh {
+    let step = 1 << lod;
+    let quads = settings.chunk_size / step;
+    let verts = quads + 1;
+    let half = settings.chunk_world_size() / 2.;
+    let uv_scale = 1. / (heightmap.size - 1) as f32;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+
+    for j in 0..verts {
+        for i in 0..verts {
+            let x = coord.x * settings.chunk_size + i * step;
+            let z = coord.y * settings.chunk_size + j * step;
+            positions.push([
+                (i * step) as f32 * settings.spacing - half,
+                heightmap.get(x, z) * settings.height_scale,
+                (j * step) as f32 * settings.spacing - half,
+            ]);
+            normals.push(heightmap.normal(x, z, settings).to_array());
+            uvs.push([x as f32 * uv_scale, z as f32 * uv_scale]);
+        }
+    }
+
+    for j in 0..quads {
+        for i in 0..quads {
+            let i00 = j * verts + i;
+            let i10 = i00 + 1;
+            let i01 = i00 + verts;
+            let i11 = i01 + 1;
+            indices.extend_from_slice(&[i00, i01, i10, i10, i01, i11]);
+        }
+    }
+
+    // Skirts: every border vertex gets a copy that is pushed down by `skirt_depth`.
+    let edges: [(Vec<u32>, Vec3); 4] = [
+        ((0..verts).collect(), Vec3::NEG_Z),
+        (
+            (0..verts).map(|i| (verts - 1) * verts + i).collect(),
+            Vec3::Z,
+        ),
+        ((0..verts).map(|j| j * verts).collect(), Vec3::NEG_X),
+        ((0..verts).map(|j| j * verts + verts - 1).collect(), Vec3::X),
+    ];
+    for (edge, outward) in edges {
+        let first_skirt_vertex = positions.len() as u32;
+        for &top in &edge {
+            let [x, y, z] = positions[top as usize];
+            positions.push([x, y - settings.skirt_depth, z]);
+            normals.push(normals[top as usize]);
+            uvs.push(uvs[top as usize]);
+        }
+        for k in 0..edge.len() - 1 {
+            let (a, b) = (edge[k], edge[k + 1]);
+            let (a_low, b_low) = (
+                first_skirt_vertex + k as u32,
+                first_skirt_vertex + k as u32 + 1,
+            );
+            let pa = Vec3::from(positions[a as usize]);
+            let pb = Vec3::from(positions[b as usize]);
+            let pa_low = Vec3::from(positions[a_low as usize]);
+            // Make sure the skirt faces outwards.
+            if (pb - pa).cross(pa_low - pa).dot(outward) >= 0. {
+                indices.extend_from_slice(&[a, b, a_low, b, b_low, a_low]);
+            } else {
+                indices.extend_from_slice(&[a, a_low, b, b, a_low, b_low]);
+            }
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+fn build_chunk_collider(
+    heightmap: &Heightmap,
+    settings: &TerrainSettings,
+    coord: UVec2,
+) -> Collider {
+    let verts = settings.chunk_size + 1;
+    // The heightfield expects heights[x][z].
+    let heights = (0..verts)
+        .map(|i| {
+            (0..verts)
+                .map(|j| {
+                    heightmap.get(
+                        coord.x * settings.chunk_size + i,
+                        coord.y * settings.chunk_size + j,
+                    ) * settings.height_scale
+                })
+                .collect()
+        })
+        .collect();
+    let size = settings.chunk_world_size();
+    Collider::heightfield(heights, Vec3::new(size, 1., size))
+}
+
+// Where the terrain meets the water, for the water's shoreline foam. It's strongest where the
+// terrain is at the water level and fades out `SHORE_FOAM_DEPTH` above and below.
+#[derive(Resource)]
+pub struct ShoreMap {
+    pub image: Handle<Image>,
+    // Absolute position of the north-west corner and the size of the covered square.
+    pub origin: Vec3,
+    pub size: f32,
+}
+
+const SHORE_FOAM_DEPTH: f32 = 4.;
+
+pub fn build_shore_map(
+    mut commands: Commands,
+    settings: Res<TerrainSettings>,
+    heightmap: Res<Heightmap>,
+    water: Query<&Transform, With<WaterBody>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let level = water
+        .iter()
+        .next()
+        .map_or(0., |transform| transform.translation.y);
+    let mut data = Vec::with_capacity((heightmap.size * heightmap.size) as usize);
+    for z in 0..heightmap.size {
+        for x in 0..heightmap.size {
+            let height = settings.origin.y + heightmap.get(x, z) * settings.height_scale;
+            let foam = 1. - ((height - level).abs() / SHORE_FOAM_DEPTH).clamp(0., 1.);
+            data.push((foam * 255.) as u8);
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: heightmap.size,
+            height: heightmap.size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::linear();
+    commands.insert_resource(ShoreMap {
+        image: images.add(image),
+        origin: settings.origin,
+        size: (heightmap.size - 1) as f32 * settings.spacing,
+    });
+}
+
+fn generate_splat_map(heightmap: &Heightmap, settings: &TerrainSettings) -> Image {
+    let mut data = Vec::with_capacity((heightmap.size * heightmap.size * 4) as usize);
+    for z in 0..heightmap.size {
+        for x in 0..heightmap.size {
+            let height = heightmap.get(x, z);
+            let steepness = 1. - heightmap.normal(x, z, settings).y;
+
+            let sand = 1. - (height / 0.05).clamp(0., 1.);
+            let snow = ((height - 0.55) / 0.1).clamp(0., 1.);
+            let rock = ((steepness - 0.15) / 0.15).clamp(0., 1.) * (1. - snow);
+            let grass = (1. - sand - rock - snow).max(0.);
+
+            data.extend([sand, grass, rock, snow].map(|weight| (weight * 255.) as u8));
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: heightmap.size,
+            height: heightmap.size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::linear();
+    image
+}
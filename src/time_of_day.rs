@@ -0,0 +1,281 @@
+/*
+Time of day and the sun.
+
+`TimeOfDay` holds the local date and clock time (`utc_offset` hours ahead of UTC) and runs
+`time_scale` times as fast as real time while flying. It starts from `time_of_day` in settings.json
+for every flight, missions can set their own start time. `[` and `]` move the clock an hour back and
+forth.
+
+The sun's azimuth and elevation are computed for the aircraft's latitude and longitude (the low
+precision formulas from the Astronomical Almanac, good to about a hundredth of a degree) and the
+`Sun` light is turned to match. Its illuminance goes from raw sunlight down through twilight to the
+night. Once the sun is far enough below the horizon, the light becomes the moon: it's approximated as
+always opposite the sun, with its brightness following the phase, plus a little starlight. Clouds
+and rain dim it further. The camera's exposure follows along so nights are dark but not black.
+*/
+
+use crate::{
+    GameState, Settings,
+    geodesy::{AircraftLocation, Geodetic},
+    input::Keymap,
+    mission::ActiveMission,
+    weather::Weather,
+};
+use bevy::{camera::Exposure, light::light_consts::lux, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+// Sun elevations in degrees and the illuminance there, interpolated logarithmically in between.
+const ILLUMINANCE_CURVE: [(f32, f32); 4] = [
+    (-12., lux::FULL_MOON_NIGHT),
+    (-6., lux::CIVIL_TWILIGHT),
+    (0., lux::CLEAR_SUNRISE),
+    (10., lux::RAW_SUNLIGHT),
+];
+// Below this, the light switches to the moon.
+const NIGHT_ELEVATION: f32 = -12.;
+// The light never comes from below this elevation, so sunsets and the moon don't light from below.
+const MIN_LIGHT_ELEVATION: f32 = 1.;
+const STARLIGHT: f32 = lux::MOONLESS_NIGHT;
+// The darkest the exposure adapts to.
+const MIN_EV100: f32 = 3.;
+// A new moon, 2000-01-06 18:14 UTC, as a julian day.
+const NEW_MOON: f64 = 2_451_550.26;
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeOfDay {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    // Local time in hours, 0..24.
+    pub hours: f32,
+    pub utc_offset: f32,
+    pub time_scale: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            year: 2025,
+            month: 6,
+            day: 21,
+            hours: 12.,
+            utc_offset: 2.,
+            time_scale: 1.,
+        }
+    }
+}
+
+impl TimeOfDay {
+    // Moves the clock by `hours`, changing the date when passing midnight.
+    pub fn advance(&mut self, hours: f32) {
+        self.hours += hours;
+        while self.hours >= 24. {
+            self.hours -= 24.;
+            self.day += 1;
+            if self.day > days_in_month(self.year, self.month) {
+                self.day = 1;
+                self.month += 1;
+                if self.month > 12 {
+                    self.month = 1;
+                    self.year += 1;
+                }
+            }
+        }
+        while self.hours < 0. {
+            self.hours += 24.;
+            if self.day > 1 {
+                self.day -= 1;
+            } else {
+                if self.month > 1 {
+                    self.month -= 1;
+                } else {
+                    self.month = 12;
+                    self.year -= 1;
+                }
+                self.day = days_in_month(self.year, self.month);
+            }
+        }
+    }
+
+    pub fn julian_day(&self) -> f64 {
+        let (mut year, mut month) = (self.year as f64, self.month as f64);
+        if month <= 2. {
+            year -= 1.;
+            month += 12.;
+        }
+        let a = (year / 100.).floor();
+        let b = 2. - a + (a / 4.).floor();
+        (365.25 * (year + 4716.)).floor() + (30.6001 * (month + 1.)).floor() + self.day as f64 + b
+            - 1524.5
+            + (self.hours - self.utc_offset) as f64 / 24.
+    }
+
+    // Azimuth (clockwise from north) and elevation of the sun in degrees.
+    pub fn sun_position(&self, location: Geodetic) -> (f32, f32) {
+        let n = self.julian_day() - 2_451_545.0;
+        let mean_longitude = (280.460 + 0.985_647_4 * n).to_radians();
+        let mean_anomaly = (357.528 + 0.985_600_3 * n).to_radians();
+        let longitude = mean_longitude
+            + 1.915_f64.to_radians() * mean_anomaly.sin()
+            + 0.020_f64.to_radians() * (2. * mean_anomaly).sin();
+        let obliquity = (23.439 - 0.000_000_4 * n).to_radians();
+
+        let right_ascension = (obliquity.cos() * longitude.sin()).atan2(longitude.cos());
+        let declination = (obliquity.sin() * longitude.sin()).asin();
+        let sidereal_time =
+            (18.697_374_558 + 24.065_709_824_419_08 * n).rem_euclid(24.) / 24. * TAU;
+        let hour_angle = sidereal_time + location.longitude.to_radians() - right_ascension;
+
+        let latitude = location.latitude.to_radians();
+        let elevation = (latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin();
+        let azimuth = (-declination.cos() * hour_angle.sin()).atan2(
+            declination.sin() * latitude.cos()
+                - declination.cos() * hour_angle.cos() * latitude.sin(),
+        );
+        (
+            azimuth.to_degrees().rem_euclid(360.) as f32,
+            elevation.to_degrees() as f32,
+        )
+    }
+
+    // How much of the moon is lit, 0 at new moon and 1 at full moon.
+    pub fn moon_illumination(&self) -> f32 {
+        let phase = ((self.julian_day() - NEW_MOON) / SYNODIC_MONTH).rem_euclid(1.) * TAU;
+        ((1. - phase.cos()) / 2.) as f32
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+// The directional light that's turned to the sun or the moon.
+#[derive(Component)]
+pub struct Sun;
+
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_systems(OnEnter(GameState::Loading), start_time_of_day)
+            .add_systems(
+                Update,
+                (
+                    advance_time.run_if(in_state(GameState::InFlight)),
+                    change_time
+                        .run_if(in_state(GameState::InFlight).or(in_state(GameState::Paused))),
+                    update_sun,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn start_time_of_day(
+    mut time_of_day: ResMut<TimeOfDay>,
+    settings: Res<Settings>,
+    mission: Option<Res<ActiveMission>>,
+) {
+    *time_of_day = settings.time_of_day.clone();
+    if let Some(hours) = mission.and_then(|mission| mission.definition.time_of_day) {
+        time_of_day.hours = hours.rem_euclid(24.);
+    }
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    let hours = time.delta_secs() * time_of_day.time_scale / 3600.;
+    time_of_day.advance(hours);
+}
+
+fn change_time(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if keyboard_input.just_pressed(keymap.time_forward) {
+        time_of_day.advance(1.);
+    }
+    if keyboard_input.just_pressed(keymap.time_backward) {
+        time_of_day.advance(-1.);
+    }
+}
+
+// Direction towards a point in the sky in world space, from azimuth and elevation in degrees.
+fn sky_direction(azimuth: f32, elevation: f32) -> Vec3 {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    Vec3::new(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        -elevation.cos() * azimuth.cos(),
+    )
+}
+
+fn sun_illuminance(elevation: f32) -> f32 {
+    let first = ILLUMINANCE_CURVE[0];
+    let last = ILLUMINANCE_CURVE[ILLUMINANCE_CURVE.len() - 1];
+    if elevation <= first.0 {
+        return first.1;
+    }
+    if elevation >= last.0 {
+        return last.1;
+    }
+    for window in ILLUMINANCE_CURVE.windows(2) {
+        let ((e0, l0), (e1, l1)) = (window[0], window[1]);
+        if elevation <= e1 {
+            let t = (elevation - e0) / (e1 - e0);
+            return (l0.ln() + (l1.ln() - l0.ln()) * t).exp();
+        }
+    }
+    last.1
+}
+
+// Azimuth is measured at the aircraft but applied in world space, which is only off by the
+// convergence of the meridians far away from the reference point.
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    location: Res<AircraftLocation>,
+    weather: Res<Weather>,
+    mut sun: Single<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    mut exposures: Query<&mut Exposure>,
+) {
+    if !location.is_changed() && !time_of_day.is_changed() && !weather.is_changed() {
+        return;
+    }
+    let (azimuth, elevation) = time_of_day.sun_position(location.geodetic);
+
+    let (direction, illuminance) = if elevation > NIGHT_ELEVATION {
+        (
+            sky_direction(azimuth, elevation.max(MIN_LIGHT_ELEVATION)),
+            sun_illuminance(elevation),
+        )
+    } else {
+        let moonlight = lux::FULL_MOON_NIGHT * time_of_day.moon_illumination();
+        (
+            sky_direction(azimuth + 180., (-elevation).max(MIN_LIGHT_ELEVATION)),
+            moonlight + STARLIGHT,
+        )
+    };
+
+    let (light, transform) = &mut *sun;
+    let illuminance = illuminance * weather.sunlight_factor();
+    light.illuminance = illuminance;
+    **transform = Transform::default().looking_to(-direction, Vec3::Y);
+
+    // A light meter's reading (ISO 100, calibration constant 250), a bit lower so full sunlight
+    // matches `Exposure::SUNLIGHT`.
+    let ev100 = (illuminance * 100. / 250.).log2().max(MIN_EV100) - 0.6;
+    for mut exposure in &mut exposures {
+        exposure.ev100 = ev100;
+    }
+}
@@ -1,10 +1,43 @@
 use bevy::prelude::*;
 
-use crate::Aircraft;
+use crate::{
+    Aircraft, FlightSimSystems, GameState,
+    aircraft_definition::AircraftDefinition,
+    aircraft_mechanics::{Engine, FlightData},
+    damage::{Crashed, Damage, Impact},
+    fuel::FuelTanks,
+    gear::LandingGear,
+    geodesy::AircraftLocation,
+    high_lift::HighLiftDevices,
+    loading::FlightStarting,
+    time_of_day::TimeOfDay,
+};
+
+// The text overlays, updated after everything else has moved.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::InFlight),
+            setup_ui.run_if(resource_exists::<FlightStarting>),
+        )
+        .add_systems(
+            Update,
+            (update_ui, update_status_ui, update_devices_ui).in_set(FlightSimSystems::Ui),
+        );
+    }
+}
 
 #[derive(Component)]
 pub struct AltitudeText;
 
+#[derive(Component)]
+pub struct StatusText;
+
+#[derive(Component)]
+pub struct DevicesText;
+
 pub fn setup_ui(mut commands: Commands) {
     commands.spawn((
         Node {
@@ -15,16 +48,132 @@ pub fn setup_ui(mut commands: Commands) {
         },
         Text::new("Altitude"),
         AltitudeText,
+        DespawnOnEnter(GameState::MainMenu),
+    ));
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: px(10.0),
+            left: px(10.0),
+            ..default()
+        },
+        Text::new(""),
+        StatusText,
+        DespawnOnEnter(GameState::MainMenu),
+    ));
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: px(10.0),
+            right: px(10.0),
+            ..default()
+        },
+        Text::new(""),
+        DevicesText,
+        DespawnOnEnter(GameState::MainMenu),
     ));
 }
 
 pub fn update_ui(
     mut altitude: Single<&mut Text, With<AltitudeText>>,
-    transform: Single<&Transform, With<Aircraft>>,
+    location: Res<AircraftLocation>,
+    time_of_day: Res<TimeOfDay>,
 ) {
+    let geodetic = location.geodetic;
+    let minutes = (time_of_day.hours * 60.) as u32;
     let alt_string = format!(
-        "Altitude: {}m",
-        &transform.translation.y.round().to_string()
+        "Altitude: {}m\nPosition: {:.5}, {:.5}\nHeading: {:03.0}° true, {:03.0}° magnetic\nTime: {}-{:02}-{:02} {:02}:{:02}{}",
+        geodetic.altitude.round(),
+        geodetic.latitude,
+        geodetic.longitude,
+        location.heading_true,
+        location.heading_magnetic,
+        time_of_day.year,
+        time_of_day.month,
+        time_of_day.day,
+        minutes / 60,
+        minutes % 60,
+        if time_of_day.time_scale != 1. {
+            format!(" (x{})", time_of_day.time_scale)
+        } else {
+            String::new()
+        },
     );
     altitude.0 = alt_string;
 }
+
+pub fn update_status_ui(
+    mut status: Single<&mut Text, With<StatusText>>,
+    aircraft: Single<(&Damage, &Engine, &FuelTanks, Option<&Crashed>), With<Aircraft>>,
+    mut impacts: MessageReader<Impact>,
+    mut last_impact: Local<String>,
+) {
+    if let Some(impact) = impacts.read().last() {
+        *last_impact = format!(
+            "Last impact: {:?} on {:?} ({:.1} m/s, {:.0} Ns){}",
+            impact.kind,
+            impact.part,
+            impact.sink_rate,
+            impact.impulse,
+            if impact.gear_up { ", gear up!" } else { "" }
+        );
+    }
+
+    let (damage, engine, tanks, crashed) = *aircraft;
+    let mut text = format!(
+        "RPM {:.0}{}  Fuel {:.0} kg ({:.1} kg/h)\nWings {:.0}%  Engine {:.0}%  Gear {:.0}%  Controls {:.0}%\n{}",
+        engine.rpm,
+        if engine.running { "" } else { " (engine off)" },
+        tanks.total(),
+        engine.fuel_flow * 3600.,
+        damage.wings * 100.,
+        damage.engine * 100.,
+        damage.gear * 100.,
+        damage.control_surfaces * 100.,
+        *last_impact,
+    );
+    if let Some(crashed) = crashed {
+        text += &format!("\n{}\nPress Backspace to respawn", crashed.reason);
+    }
+    status.0 = text;
+}
+
+pub fn update_devices_ui(
+    mut text: Single<&mut Text, With<DevicesText>>,
+    definition: Res<AircraftDefinition>,
+    aircraft: Single<(&HighLiftDevices, &LandingGear, &FlightData), With<Aircraft>>,
+) {
+    let (devices, gear, data) = *aircraft;
+    let moving = |value: f32| {
+        if value > 0. && value < 1. {
+            "moving"
+        } else if value >= 1. {
+            "out"
+        } else {
+            "in"
+        }
+    };
+    text.0 = format!(
+        "Airspeed: {:.0} kt\nFlaps: {:.0}°\nSlats: {}\nSpeed brake: {}\nSpoilers: {}\nGear: {}",
+        data.airspeed * 1.943_844,
+        devices.flap_angle,
+        moving(devices.slats),
+        moving(devices.speed_brake),
+        moving(devices.spoilers),
+        if gear.down_and_locked() {
+            "down"
+        } else if gear.position <= 0. {
+            "up"
+        } else {
+            "moving"
+        },
+    );
+
+    // Landing flaps set without the gear down.
+    let landing_flaps = devices.flap_detent + 1 >= definition.flaps.detents.len();
+    if landing_flaps && devices.flap_detent > 0 && !gear.down_and_locked() {
+        text.0 += "\nWARNING: GEAR UP";
+    }
+}
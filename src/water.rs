@@ -0,0 +1,335 @@
+/*
+Water physics.
+
+Water bodies are planes at their transform's height with waves on top. Instead of colliding with
+them, every float point of the aircraft (see aircraft_definition.rs) that is below the surface pushes
+the aircraft up with the weight of the water it displaces and gets slowed down by hydrodynamic drag.
+
+The waves come from the wind (see `WaterWaves::from_wind`): a fully developed sea for the wind speed,
+made of four sine waves around the wind's direction. The water mesh follows the camera and its cells
+are a couple of meters wide in the middle and get wider further out, so every wave fades out where
+the cells get too wide to draw it (its `reach`). Waves too short even for the middle are left out,
+and the small ripples only exist in the shader's normal map. `WaterWaves::height` is the same sum as
+in assets/shaders/water_material.wgsl, so the physics and the camera see the surface that's drawn.
+Both work in absolute coordinates, so the waves don't jump when the floating origin or the mesh
+moves.
+
+The moment the aircraft touches the water, the sink rate decides what happens: too fast is a
+crash, otherwise seaplanes just land and everything else ditches.
+*/
+
+use crate::{
+    Aircraft, FollowCamera, aircraft_definition::AircraftDefinition, aircraft_mechanics::Wind,
+    floating_origin::FloatingOrigin, loading::RequiredAssets, ssr,
+};
+use avian3d::prelude::*;
+use bevy::{
+    asset::RenderAssetUsages,
+    mesh::{Indices, PrimitiveTopology},
+    pbr::ExtendedMaterial,
+    prelude::*,
+};
+use std::f32::consts::TAU;
+
+const WATER_DENSITY: f32 = 1000.;
+const GRAVITY: f32 = 9.81;
+pub const WATER_HALF_SIZE: f32 = 10_000.;
+// Meters between the vertices of the water mesh in its middle, how much wider each ring of cells
+// gets further out, and the widest they get.
+const WATER_MESH_NEAR_SPACING: f32 = 2.;
+const WATER_MESH_GROWTH: f32 = 1.02;
+pub const WATER_MESH_SPACING: f32 = 40.;
+// Vertices per wavelength a wave needs to be drawn.
+const VERTICES_PER_WAVE: f32 = 4.;
+// Wavelength, direction (degrees from the wind) and height of every wave, relative to the peak
+// wavelength and the significant wave height.
+const WAVE_COMPONENTS: [(f32, f32, f32); 4] = [
+    (1.0, 0., 0.5),
+    (1.7, 20., 0.3),
+    (0.6, -30., 0.3),
+    (2.6, 10., 0.15),
+];
+
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct WaterWaves {
+    // Every wave as direction (x, z), wavelength and amplitude, in meters.
+    pub waves: [Vec4; 4],
+    // How far from the middle of the water mesh each wave is drawn, it fades out over the second
+    // half of that.
+    pub reach: Vec4,
+    // Direction the wind pushes the ripples in, scaled by how choppy they are (0..1).
+    pub chop: Vec2,
+}
+
+impl WaterWaves {
+    pub fn from_wind(wind: Vec3) -> Self {
+        let speed = wind.xz().length();
+        let direction = wind.xz().normalize_or(Vec2::NEG_Y);
+        // Pierson-Moskowitz: significant wave height and peak period for the wind speed.
+        let wave_height = (0.0246 * speed * speed).min(8.);
+        let period = 0.73 * speed;
+        let peak_wavelength = (GRAVITY * period * period / TAU).max(1.);
+
+        let waves = WAVE_COMPONENTS.map(|(wavelength, angle, height)| {
+            let wavelength = peak_wavelength * wavelength;
+            let direction = Vec2::from_angle(angle.to_radians()).rotate(direction);
+            // Too short for the mesh, or steep enough to break.
+            let amplitude = if wavelength < WATER_MESH_NEAR_SPACING * VERTICES_PER_WAVE {
+                0.
+            } else {
+                (wave_height / 2. * height).min(wavelength * 0.07)
+            };
+            direction.extend(wavelength).extend(amplitude)
+        });
+        Self {
+            waves,
+            reach: Vec4::from_array(waves.map(|wave| wave_reach(wave.z))),
+            chop: direction * (speed / 15.).min(1.),
+        }
+    }
+
+    // Height of the waves above the water body at an absolute position, `distance` meters from the
+    // middle of the water mesh.
+    pub fn height(&self, position: Vec2, distance: f32, time: f32) -> f32 {
+        self.waves
+            .iter()
+            .zip(self.reach.to_array())
+            .map(|(wave, reach)| {
+                let k = TAU / wave.z;
+                let omega = (GRAVITY * k).sqrt();
+                let fade = (2. * (1. - distance / reach)).clamp(0., 1.);
+                wave.w * fade * (k * wave.xy().dot(position) - omega * time).sin()
+            })
+            .sum()
+    }
+}
+
+// Distance from the middle of the water mesh where the cells get too wide for a wavelength. Going
+// out, cells are `WATER_MESH_GROWTH` times as wide as the last, so their width grows linearly with
+// the distance until it reaches `WATER_MESH_SPACING`.
+fn wave_reach(wavelength: f32) -> f32 {
+    let spacing = wavelength / VERTICES_PER_WAVE;
+    if spacing >= WATER_MESH_SPACING {
+        // Everywhere on the mesh.
+        return WATER_HALF_SIZE * 4.;
+    }
+    ((spacing - WATER_MESH_NEAR_SPACING) / (WATER_MESH_GROWTH - 1.)).max(1.)
+}
+
+#[derive(Component)]
+pub struct WaterBody {
+    // Half the size of the surface along x and z, in world units.
+    pub half_size: Vec2,
+}
+
+#[derive(Component, Default)]
+pub struct WaterContact {
+    pub in_water: bool,
+}
+
+#[derive(Message, Debug)]
+pub enum WaterImpact {
+    Landed,
+    Ditched { sink_rate: f32 },
+    Crashed { sink_rate: f32 },
+}
+
+// The sea, rendered with screen space reflections if the graphics settings have ssr and with
+// reflections of the sky otherwise. Its physics are part of the flight model.
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<
+            ExtendedMaterial<StandardMaterial, ssr::Water>,
+        >::default())
+            .init_resource::<RequiredAssets>()
+            .add_systems(Startup, setup_water)
+            .add_systems(
+                Update,
+                (move_water_with_camera, ssr::update_water_material).chain(),
+            );
+    }
+}
+
+fn setup_water(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    meshes: ResMut<Assets<Mesh>>,
+    water_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, ssr::Water>>>,
+    mut images: ResMut<Assets<Image>>,
+    mut required: ResMut<RequiredAssets>,
+) {
+    let water = ssr::spawn_water(
+        &mut commands,
+        &asset_server,
+        meshes,
+        water_materials,
+        &mut images,
+        &mut required,
+    );
+    commands.entity(water).insert(WaterBody {
+        half_size: Vec2::splat(WATER_HALF_SIZE),
+    });
+}
+
+// The sea's mesh: a grid with cells `WATER_MESH_NEAR_SPACING` wide in the middle that grow towards
+// the edges, see `wave_reach`. The shader computes its own uvs, from the absolute position.
+pub fn water_mesh() -> Mesh {
+    let mut edges = vec![0.];
+    let mut spacing = WATER_MESH_NEAR_SPACING;
+    while let Some(&last) = edges.last()
+        && last < WATER_HALF_SIZE
+    {
+        edges.push((last + spacing).min(WATER_HALF_SIZE));
+        spacing = (spacing * WATER_MESH_GROWTH).min(WATER_MESH_SPACING);
+    }
+    let axis: Vec<f32> = edges
+        .iter()
+        .rev()
+        .map(|edge| -edge)
+        .chain(edges[1..].iter().copied())
+        .collect();
+
+    let count = axis.len() as u32;
+    let positions: Vec<[f32; 3]> = axis
+        .iter()
+        .flat_map(|&z| axis.iter().map(move |&x| [x, 0., z]))
+        .collect();
+    let mut indices = Vec::with_capacity(((count - 1) * (count - 1) * 6) as usize);
+    for z in 0..count - 1 {
+        for x in 0..count - 1 {
+            let i = z * count + x;
+            indices.extend([i, i + count, i + 1, i + 1, i + count, i + count + 1]);
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; positions.len()])
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; positions.len()])
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+// The water mesh is finest in its middle, so it moves along with the camera. Only in whole outer
+// cells, in absolute coordinates, so the coarse vertices far away don't swim over the waves.
+pub fn move_water_with_camera(
+    origin: Res<FloatingOrigin>,
+    camera: Single<&GlobalTransform, With<FollowCamera>>,
+    mut water: Query<&mut Transform, With<WaterBody>>,
+) {
+    let cell = WATER_MESH_SPACING as f64;
+    let snapped = (origin.absolute(camera.translation()) / cell).round() * cell;
+    let center = origin.local(snapped);
+    for mut transform in &mut water {
+        transform.translation.x = center.x;
+        transform.translation.z = center.z;
+    }
+}
+
+pub fn update_water_waves(wind: Res<Wind>, mut waves: ResMut<WaterWaves>) {
+    waves.set_if_neq(WaterWaves::from_wind(wind.velocity));
+}
+
+// Height of the water surface, waves included, below `point`, if there is any water there. `time`
+// has to be `Time::elapsed_secs_wrapped`, like the shader's.
+pub fn water_level(
+    point: Vec3,
+    time: f32,
+    water: &Query<(&WaterBody, &GlobalTransform)>,
+    waves: &WaterWaves,
+    origin: &FloatingOrigin,
+) -> Option<f32> {
+    let center = water
+        .iter()
+        .filter(|(body, transform)| {
+            let offset = (point - transform.translation()).xz().abs();
+            offset.x <= body.half_size.x && offset.y <= body.half_size.y
+        })
+        .map(|(_, transform)| transform.translation())
+        .max_by(|a, b| a.y.total_cmp(&b.y))?;
+    let distance = (point - center).xz().length();
+    Some(center.y + waves.height(origin.absolute(point).xz().as_vec2(), distance, time))
+}
+
+pub fn water_forces(
+    time: Res<Time>,
+    definition: Res<AircraftDefinition>,
+    waves: Res<WaterWaves>,
+    origin: Res<FloatingOrigin>,
+    water: Query<(&WaterBody, &GlobalTransform)>,
+    mut aircraft: Query<(Forces, &GlobalTransform, &mut WaterContact), With<Aircraft>>,
+    mut impacts: MessageWriter<WaterImpact>,
+) {
+    for (mut forces, transform, mut contact) in &mut aircraft {
+        let mut touching = false;
+
+        for float in &definition.floats {
+            let point = transform.transform_point(float.position);
+            let Some(level) =
+                water_level(point, time.elapsed_secs_wrapped(), &water, &waves, &origin)
+            else {
+                continue;
+            };
+            let depth = level - point.y;
+            if depth <= 0. {
+                continue;
+            }
+            touching = true;
+
+            let submerged = (depth / float.draft).min(1.);
+            let buoyancy = Vec3::Y * WATER_DENSITY * GRAVITY * float.volume * submerged;
+            let velocity = forces.velocity_at_point(point);
+            let drag = -velocity * velocity.length() * definition.water_drag * submerged;
+            forces.apply_force_at_point(buoyancy + drag, point);
+        }
+
+        if touching && !contact.in_water {
+            let sink_rate = -forces.linear_velocity().y;
+            let impact = if sink_rate > definition.max_water_sink_rate {
+                WaterImpact::Crashed { sink_rate }
+            } else if definition.seaplane {
+                WaterImpact::Landed
+            } else {
+                WaterImpact::Ditched { sink_rate }
+            };
+            info!("Water impact: {:?}", impact);
+            impacts.write(impact);
+        }
+        contact.in_water = touching;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_winds_make_waves_near_the_camera() {
+        // From the Clear preset's 3 m/s up to the Rain preset's 10 m/s.
+        for speed in [3., 6., 8., 10.] {
+            let waves = WaterWaves::from_wind(Vec3::X * speed);
+            // Longer than the longest wave at 10 m/s, at one moment so the samples don't ride
+            // along with a wave.
+            let highest = (0..320)
+                .map(|i| waves.height(Vec2::new(i as f32 * 0.7, 3.), 0., 0.))
+                .fold(0., f32::max);
+            // A tenth of the significant wave height, at least.
+            assert!(
+                highest > 0.1 * 0.0246 * speed * speed,
+                "{speed} m/s: {highest} m"
+            );
+        }
+    }
+
+    #[test]
+    fn short_waves_fade_out_away_from_the_camera() {
+        // Every wave is shorter than 20 m, too short for the cells far out.
+        let waves = WaterWaves::from_wind(Vec3::X * 3.);
+        assert!(waves.reach.max_element() < 1000.);
+        assert_eq!(waves.height(Vec2::new(1.3, 2.), 5000., 0.), 0.);
+    }
+}
@@ -0,0 +1,402 @@
+/*
+Weather.
+
+`Weather` is built from one of the presets (clear, scattered, overcast, fog, rain) and holds the
+visibility, the cloud layers, how hard it rains and the wind and turbulence. Every flight starts with
+`weather` from settings.json, or the mission's, and F7 cycles through the presets while flying.
+The fields are public, so anything can change the weather at runtime and it gets applied the next
+frame.
+
+- Visibility is distance fog on the flight camera, darkening along with the sun. It's never further
+  than the view distance from the graphics settings, so the far plane doesn't show.
+- A cloud layer is a stack of a few big transparent planes with a noise texture, as thick as the
+  layer. The planes follow the aircraft in steps of the texture's size, so the clouds stay put.
+- Rain is a box of streaks around the camera that fall and drift with the wind.
+- The wind and turbulence go to `Wind`, which the flight model uses for the airspeed (see
+  aircraft_mechanics.rs).
+- Clouds and rain dim the sunlight (see time_of_day.rs).
+*/
+
+use crate::{
+    Aircraft, FollowCamera, GameState, Settings, aircraft_mechanics::Wind,
+    floating_origin::FloatingOrigin, graphics::Graphics, input::Keymap, mission::ActiveMission,
+    time_of_day::Sun,
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
+    light::light_consts::lux,
+    math::Affine2,
+    pbr::{DistanceFog, FogFalloff},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::{Deserialize, Serialize};
+
+// Size of one repetition of the cloud texture in meters, a multiple of the floating origin's cells.
+const CLOUD_TILE_SIZE: f32 = 4000.;
+const CLOUD_TEXTURE_SIZE: u32 = 256;
+// How many times the texture repeats across a cloud plane.
+const CLOUD_TILES: f32 = 12.;
+const CLOUD_SLICES: usize = 4;
+const RAIN_DROPS: usize = 1500;
+// Half the size of the box of rain around the camera.
+const RAIN_EXTENT: Vec3 = Vec3::new(25., 15., 25.);
+const RAIN_FALL_SPEED: f32 = 9.;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WeatherPreset {
+    #[default]
+    Clear,
+    Scattered,
+    Overcast,
+    Fog,
+    Rain,
+}
+
+impl WeatherPreset {
+    const ALL: [Self; 5] = [
+        Self::Clear,
+        Self::Scattered,
+        Self::Overcast,
+        Self::Fog,
+        Self::Rain,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|preset| *preset == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudLayer {
+    // Meters above sea level, of the layer's bottom.
+    pub altitude: f32,
+    pub thickness: f32,
+    // How much of the sky it covers, 0..1.
+    pub coverage: f32,
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct Weather {
+    pub preset: WeatherPreset,
+    // Meters
+    pub visibility: f32,
+    pub clouds: Vec<CloudLayer>,
+    // 0..1
+    pub precipitation: f32,
+    // Degrees true the wind comes from, and its speed in m/s.
+    pub wind_direction: f32,
+    pub wind_speed: f32,
+    pub turbulence: f32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self::preset(WeatherPreset::Clear)
+    }
+}
+
+impl Weather {
+    pub fn preset(preset: WeatherPreset) -> Self {
+        let layer = |altitude, thickness, coverage| CloudLayer {
+            altitude,
+            thickness,
+            coverage,
+        };
+        let (visibility, clouds, precipitation, wind_direction, wind_speed, turbulence) =
+            match preset {
+                WeatherPreset::Clear => (50_000., vec![], 0., 270., 3., 0.3),
+                WeatherPreset::Scattered => (
+                    30_000.,
+                    vec![layer(1500., 300., 0.35), layer(4000., 200., 0.2)],
+                    0.,
+                    250.,
+                    6.,
+                    1.,
+                ),
+                WeatherPreset::Overcast => {
+                    (15_000., vec![layer(800., 600., 0.95)], 0., 230., 8., 1.5)
+                }
+                WeatherPreset::Fog => (800., vec![layer(150., 200., 0.8)], 0., 180., 1., 0.1),
+                WeatherPreset::Rain => (5000., vec![layer(500., 800., 1.)], 1., 220., 10., 3.),
+            };
+        Self {
+            preset,
+            visibility,
+            clouds,
+            precipitation,
+            wind_direction,
+            wind_speed,
+            turbulence,
+        }
+    }
+
+    // How much of the sunlight gets through the clouds.
+    pub fn sunlight_factor(&self) -> f32 {
+        let cover = self
+            .clouds
+            .iter()
+            .fold(1., |light, layer| light * (1. - layer.coverage * 0.9));
+        cover * (1. - self.precipitation * 0.5)
+    }
+
+    fn wind_velocity(&self) -> Vec3 {
+        // Blowing towards the opposite of where it comes from, with north at -Z.
+        let towards = (self.wind_direction + 180.).to_radians();
+        Vec3::new(towards.sin(), 0., -towards.cos()) * self.wind_speed
+    }
+}
+
+#[derive(Component)]
+pub struct CloudSlice {
+    altitude: f32,
+}
+
+#[derive(Component)]
+pub struct RainDrop;
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .add_systems(OnEnter(GameState::Loading), start_weather)
+            .add_systems(
+                Update,
+                (
+                    cycle_weather
+                        .run_if(in_state(GameState::InFlight).or(in_state(GameState::Paused))),
+                    (apply_wind, spawn_clouds, spawn_rain).run_if(resource_changed::<Weather>),
+                    (update_fog, move_clouds, move_rain),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn start_weather(
+    mut weather: ResMut<Weather>,
+    settings: Res<Settings>,
+    mission: Option<Res<ActiveMission>>,
+) {
+    let preset = mission
+        .and_then(|mission| mission.definition.weather)
+        .unwrap_or(settings.weather);
+    *weather = Weather::preset(preset);
+}
+
+fn cycle_weather(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut weather: ResMut<Weather>,
+) {
+    if keyboard_input.just_pressed(keymap.weather) {
+        *weather = Weather::preset(weather.preset.next());
+        info!("Weather: {:?}", weather.preset);
+    }
+}
+
+fn apply_wind(weather: Res<Weather>, mut wind: ResMut<Wind>) {
+    wind.velocity = weather.wind_velocity();
+    wind.turbulence = weather.turbulence;
+}
+
+// Tileable value noise with a few octaves, 0..1.
+fn cloud_noise(x: u32, y: u32, seed: u32) -> f32 {
+    let hash = |x: u32, y: u32, period: u32| {
+        let (x, y) = (x % period, y % period);
+        let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263) ^ seed;
+        h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+        (h ^ (h >> 16)) as f32 / u32::MAX as f32
+    };
+    let mut value = 0.;
+    let mut amplitude = 0.5;
+    let mut total = 0.;
+    for octave in 0..5 {
+        let period = 4 << octave;
+        let cell = CLOUD_TEXTURE_SIZE as f32 / period as f32;
+        let (fx, fy) = (x as f32 / cell, y as f32 / cell);
+        let (x0, y0) = (fx.floor() as u32, fy.floor() as u32);
+        let (tx, ty) = (fx.fract(), fy.fract());
+        let (tx, ty) = (tx * tx * (3. - 2. * tx), ty * ty * (3. - 2. * ty));
+        let top = hash(x0, y0, period).lerp(hash(x0 + 1, y0, period), tx);
+        let bottom = hash(x0, y0 + 1, period).lerp(hash(x0 + 1, y0 + 1, period), tx);
+        value += top.lerp(bottom, ty) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+    }
+    value / total
+}
+
+fn cloud_texture(coverage: f32, seed: u32) -> Image {
+    let mut data = Vec::with_capacity((CLOUD_TEXTURE_SIZE * CLOUD_TEXTURE_SIZE * 4) as usize);
+    for y in 0..CLOUD_TEXTURE_SIZE {
+        for x in 0..CLOUD_TEXTURE_SIZE {
+            // The noise is roughly 0.25..0.75, more coverage lowers the threshold.
+            let density = ((cloud_noise(x, y, seed) - (0.75 - coverage * 0.5)) * 6.).clamp(0., 1.);
+            data.extend_from_slice(&[255, 255, 255, (density * 255.) as u8]);
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: CLOUD_TEXTURE_SIZE,
+            height: CLOUD_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::linear()
+    });
+    image
+}
+
+fn spawn_clouds(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    slices: Query<Entity, With<CloudSlice>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for slice in &slices {
+        commands.entity(slice).despawn();
+    }
+
+    let size = CLOUD_TILE_SIZE * CLOUD_TILES;
+    let mesh = meshes.add(Plane3d::default().mesh().size(size, size));
+    for (index, layer) in weather.clouds.iter().enumerate() {
+        for slice in 0..CLOUD_SLICES {
+            // The middle slices are denser than the top and bottom ones.
+            let height = slice as f32 / (CLOUD_SLICES - 1) as f32;
+            let density = 1. - (height * 2. - 1.).abs() * 0.5;
+            let texture = images.add(cloud_texture(
+                layer.coverage * density,
+                (index * CLOUD_SLICES + slice) as u32,
+            ));
+            commands.spawn((
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(texture),
+                    alpha_mode: AlphaMode::Blend,
+                    cull_mode: None,
+                    double_sided: true,
+                    perceptual_roughness: 1.,
+                    uv_transform: Affine2::from_scale(Vec2::splat(CLOUD_TILES)),
+                    ..default()
+                })),
+                Transform::from_xyz(0., layer.altitude + layer.thickness * height, 0.),
+                CloudSlice {
+                    altitude: layer.altitude + layer.thickness * height,
+                },
+            ));
+        }
+    }
+}
+
+fn spawn_rain(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    drops: Query<Entity, With<RainDrop>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for drop in &drops {
+        commands.entity(drop).despawn();
+    }
+
+    let count = (RAIN_DROPS as f32 * weather.precipitation) as usize;
+    if count == 0 {
+        return;
+    }
+    let mesh = meshes.add(Cuboid::new(0.01, 0.5, 0.01));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgba(0.7, 0.75, 0.8, 0.35),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    for index in 0..count {
+        // Spread evenly through the box, the golden ratio avoids visible patterns.
+        let t = index as f32;
+        let offset = Vec3::new(
+            (t * 0.618_034).fract(),
+            (t * 0.381_966 + 0.5).fract(),
+            (t * 0.754_878).fract(),
+        ) * 2.
+            - Vec3::ONE;
+        commands.spawn((
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_translation(offset * RAIN_EXTENT),
+            RainDrop,
+        ));
+    }
+}
+
+// Fog has no lighting of its own, so it's darkened along with the sun. New cameras get their fog
+// once, after that it's only updated.
+fn update_fog(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    graphics: Res<Graphics>,
+    sun: Single<&DirectionalLight, With<Sun>>,
+    new_cameras: Query<Entity, (Added<FollowCamera>, Without<DistanceFog>)>,
+    mut fogs: Query<&mut DistanceFog, With<FollowCamera>>,
+) {
+    let brightness = (sun.illuminance / lux::CLEAR_SUNRISE).clamp(0.02, 1.);
+    let color = Color::from((LinearRgba::rgb(0.31, 0.37, 0.45) * brightness).with_alpha(1.));
+    let falloff = FogFalloff::from_visibility(weather.visibility.min(graphics.view_distance));
+    for camera in &new_cameras {
+        commands.entity(camera).insert(DistanceFog {
+            color,
+            falloff: falloff.clone(),
+            ..default()
+        });
+    }
+    for mut fog in &mut fogs {
+        fog.color = color;
+        fog.falloff = falloff.clone();
+    }
+}
+
+fn move_clouds(
+    origin: Res<FloatingOrigin>,
+    aircraft: Single<&Transform, With<Aircraft>>,
+    mut slices: Query<(&mut Transform, &CloudSlice), Without<Aircraft>>,
+) {
+    // Snapped to whole tiles in absolute coordinates, so moving the plane doesn't move the clouds.
+    let absolute = origin.absolute(aircraft.translation);
+    let tile = CLOUD_TILE_SIZE as f64;
+    let snapped = (absolute / tile).round() * tile;
+    let center = origin.local(snapped.with_y(0.));
+    for (mut transform, slice) in &mut slices {
+        transform.translation = center.with_y(slice.altitude);
+    }
+}
+
+fn move_rain(
+    time: Res<Time>,
+    wind: Res<Wind>,
+    camera: Single<&GlobalTransform, With<FollowCamera>>,
+    mut drops: Query<&mut Transform, With<RainDrop>>,
+) {
+    let center = camera.translation();
+    let velocity = wind.velocity + wind.gust - Vec3::Y * RAIN_FALL_SPEED;
+    // Streaks point along the way they fall.
+    let rotation = Quat::from_rotation_arc(Vec3::Y, -velocity.normalize());
+    for mut transform in &mut drops {
+        let offset = transform.translation - center + velocity * time.delta_secs();
+        // Drops leaving the box come back in on the other side.
+        let wrapped = (offset + RAIN_EXTENT).rem_euclid(RAIN_EXTENT * 2.) - RAIN_EXTENT;
+        transform.translation = center + wrapped;
+        transform.rotation = rotation;
+    }
+}
@@ -0,0 +1,69 @@
+/*
+Weight and balance.
+
+The total mass and center of mass of the aircraft are the empty aircraft plus whatever is in the
+fuel tanks and payload stations (seats, cargo). They are recomputed whenever fuel or payload
+change and handed to avian through `Mass` and `CenterOfMass`.
+*/
+
+use crate::{Aircraft, aircraft_definition::AircraftDefinition, fuel::FuelTanks};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+// Mass in kg on every payload station, in the same order as in the aircraft definition.
+#[derive(Component, Debug, Clone)]
+pub struct Payload(pub Vec<f32>);
+
+impl Payload {
+    pub fn default_for(definition: &AircraftDefinition) -> Self {
+        Self(
+            definition
+                .stations
+                .iter()
+                .map(|station| station.default_mass)
+                .collect(),
+        )
+    }
+}
+
+// Total mass and center of mass in aircraft space.
+pub fn mass_properties(
+    definition: &AircraftDefinition,
+    tanks: &FuelTanks,
+    payload: &Payload,
+) -> (f32, Vec3) {
+    let items = std::iter::once((definition.empty_mass, definition.empty_center_of_mass))
+        .chain(
+            definition
+                .tanks
+                .iter()
+                .zip(&tanks.0)
+                .map(|(tank, &mass)| (mass, tank.position)),
+        )
+        .chain(
+            definition
+                .stations
+                .iter()
+                .zip(&payload.0)
+                .map(|(station, &mass)| (mass, station.position)),
+        );
+
+    let (mass, moment) = items.fold((0., Vec3::ZERO), |(mass, moment), (m, position)| {
+        (mass + m, moment + position * m)
+    });
+    (mass, moment / mass)
+}
+
+pub fn update_mass_properties(
+    definition: Res<AircraftDefinition>,
+    mut aircraft: Query<
+        (&FuelTanks, &Payload, &mut Mass, &mut CenterOfMass),
+        (With<Aircraft>, Or<(Changed<FuelTanks>, Changed<Payload>)>),
+    >,
+) {
+    for (tanks, payload, mut mass, mut center_of_mass) in &mut aircraft {
+        let (total, center) = mass_properties(&definition, tanks, payload);
+        mass.0 = total;
+        center_of_mass.0 = center;
+    }
+}
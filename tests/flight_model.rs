@@ -0,0 +1,166 @@
+/*
+Flight model tests, flown headless (see src/headless.rs) with the stock Ferris. Every update
+advances the simulation by exactly `TIMESTEP`, so the results don't depend on the machine running
+them.
+*/
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_fs::{
+    InputAxis,
+    aircraft_definition::AircraftDefinition,
+    aircraft_mechanics::{FlightData, air_density},
+    damage::{Crashed, Damage},
+    gear::LandingGear,
+    headless::{TIMESTEP, headless_app, spawn_ground, spawn_headless_aircraft},
+};
+
+// The headless aircraft's box reaches 1.5 m below its origin.
+const GROUND_CLEARANCE: f32 = 1.55;
+
+// Where the Ferris flies level at 300 m with the gear down and the flaps up: the speed where the
+// wing carries it with the fuselage level, and the throttle that makes up for the drag there.
+const CRUISE_SPEED: f32 = 79.;
+const CRUISE_THROTTLE: f32 = 0.173;
+
+fn flight(definition: AircraftDefinition) -> App {
+    let mut app = headless_app(definition);
+    app.finish();
+    app.cleanup();
+    spawn_ground(&mut app);
+    app
+}
+
+fn run(app: &mut App, seconds: f64) {
+    for _ in 0..(seconds / TIMESTEP).round() as usize {
+        app.update();
+    }
+}
+
+fn altitude(app: &App, aircraft: Entity) -> f32 {
+    app.world()
+        .get::<Transform>(aircraft)
+        .unwrap()
+        .translation
+        .y
+}
+
+#[test]
+fn full_throttle_reaches_takeoff_speed_within_fifteen_seconds() {
+    let definition = AircraftDefinition::fetch("ferris");
+    // 1.2 times the stall speed at the maximum takeoff mass.
+    let takeoff_speed = 1.2
+        * (2. * definition.max_takeoff_mass * 9.81
+            / (air_density(0.) * definition.wing_area * definition.max_lift))
+            .sqrt();
+    let mut app = flight(definition);
+    let start = Transform::from_xyz(0., GROUND_CLEARANCE, 0.);
+    let aircraft = spawn_headless_aircraft(&mut app, start);
+
+    // Settle on the gear at idle first.
+    app.world_mut().resource_mut::<InputAxis>().throttle = 0.;
+    run(&mut app, 2.);
+    assert!(
+        app.world()
+            .get::<LandingGear>(aircraft)
+            .unwrap()
+            .weight_on_wheels
+    );
+    let ground_altitude = altitude(&app, aircraft);
+
+    app.world_mut().resource_mut::<InputAxis>().throttle = 1.;
+    let mut reached_takeoff_speed = None;
+    let mut lifted_off = None;
+    for update in 1..=(20. / TIMESTEP).round() as usize {
+        app.update();
+        let time = update as f64 * TIMESTEP;
+        let airspeed = app.world().get::<FlightData>(aircraft).unwrap().airspeed;
+        if reached_takeoff_speed.is_none() && airspeed >= takeoff_speed {
+            reached_takeoff_speed = Some(time);
+        }
+        let airborne = !app
+            .world()
+            .get::<LandingGear>(aircraft)
+            .unwrap()
+            .weight_on_wheels;
+        if airborne && altitude(&app, aircraft) > ground_altitude + 5. {
+            lifted_off = Some(time);
+            break;
+        }
+    }
+
+    let reached_takeoff_speed =
+        reached_takeoff_speed.expect("takeoff speed not reached within 20 seconds");
+    assert!(
+        reached_takeoff_speed < 15.,
+        "took {reached_takeoff_speed:.1} s to reach {takeoff_speed:.0} m/s"
+    );
+    // The wing lifts it off without any input once it's fast enough.
+    lifted_off.expect("not 5 m up within 20 seconds at full throttle");
+    assert!(app.world().get::<Crashed>(aircraft).is_none());
+    assert!(app.world().get::<LinearVelocity>(aircraft).unwrap().y > 0.);
+}
+
+#[test]
+fn level_flight_trims() {
+    let mut app = flight(AircraftDefinition::fetch("ferris"));
+    let aircraft = spawn_headless_aircraft(&mut app, Transform::from_xyz(0., 300., 0.));
+    // Level at cruise speed, with the engine spooling up from idle.
+    app.world_mut().resource_mut::<InputAxis>().throttle = CRUISE_THROTTLE;
+    app.world_mut()
+        .entity_mut(aircraft)
+        .insert(LinearVelocity(Vec3::NEG_Z * CRUISE_SPEED));
+    app.update();
+    let start_altitude = altitude(&app, aircraft);
+
+    run(&mut app, 30.);
+
+    let world = app.world();
+    let transform = world.get::<Transform>(aircraft).unwrap();
+    let data = world.get::<FlightData>(aircraft).unwrap();
+    assert!(
+        (transform.translation.y - start_altitude).abs() < 10.,
+        "drifted from {start_altitude:.1} m to {:.1} m",
+        transform.translation.y
+    );
+    assert!(
+        data.vertical_speed.abs() < 0.5,
+        "vertical speed {:.2} m/s after 30 seconds",
+        data.vertical_speed
+    );
+    assert!(
+        (data.airspeed - CRUISE_SPEED).abs() < 5.,
+        "airspeed {:.1} m/s after 30 seconds",
+        data.airspeed
+    );
+    assert!(
+        transform.forward().y.abs() < 3_f32.to_radians().sin(),
+        "not level anymore"
+    );
+}
+
+#[test]
+fn destroyed_gear_collapses() {
+    let mut app = flight(AircraftDefinition::fetch("ferris"));
+    let start = Transform::from_xyz(0., GROUND_CLEARANCE, 0.);
+    let aircraft = spawn_headless_aircraft(&mut app, start);
+    app.world_mut().resource_mut::<InputAxis>().throttle = 0.;
+    run(&mut app, 2.);
+
+    app.world_mut().get_mut::<Damage>(aircraft).unwrap().gear = 0.;
+    app.world_mut()
+        .entity_mut(aircraft)
+        .insert(LinearVelocity(Vec3::NEG_Z * 20.));
+    run(&mut app, 2.);
+
+    let world = app.world();
+    let gear = world.get::<LandingGear>(aircraft).unwrap();
+    assert!(!gear.down_and_locked());
+    assert!(!gear.weight_on_wheels);
+    // Sliding on the belly stops it far quicker than rolling would.
+    let speed = world.get::<LinearVelocity>(aircraft).unwrap().length();
+    assert!(
+        speed < 15.,
+        "still at {speed:.1} m/s after 2 seconds on the belly"
+    );
+}